@@ -1,9 +1,14 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf};
 
-use chord_rs_core::Client;
+use chord_rs_core::{
+    client::{ClientConfig, TlsConfig, TlsIdentity},
+    Client,
+};
 use clap::{arg, command, Args, Parser, Subcommand, ValueEnum};
 
-use crate::commands::{lookup::Lookup, ping::Ping, CommandExecute, CommandResult, Error};
+use crate::commands::{
+    lookup::Lookup, ping::Ping, trace::Trace, CommandExecute, CommandResult, Context, Error,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -12,10 +17,17 @@ pub(crate) struct Cli {
     #[arg(long, value_name = "ADDRESS:PORT")]
     pub(crate) ring: SocketAddr,
 
+    /// Transport used to communicate with the node
+    #[arg(short, long, value_enum, default_value_t = Transport::Capnp)]
+    pub(crate) transport: Transport,
+
     /// Set the log level
     #[arg(short('L'), long, value_name = "LEVEL", value_enum, default_value_t = LogLevel::Warn)]
     pub(crate) log_level: LogLevel,
 
+    #[command(flatten)]
+    pub(crate) tls: TlsArgs,
+
     /// Subcommand
     #[command(subcommand)]
     pub(crate) command: Commands,
@@ -26,24 +38,31 @@ pub(crate) enum Commands {
     /// Lookup a key in the ring, returns the node that owns the key
     Lookup(LookupArgs),
 
+    /// Lookup a key in the ring hop by hop, shows every node the lookup visits
+    Trace(LookupArgs),
+
     /// Ping a node in the ring
     Ping(PingArgs),
 }
 
 #[async_trait::async_trait]
 impl CommandExecute for Commands {
-    async fn execute<C>(&self, client: C) -> Result<CommandResult, Error>
+    async fn execute<C>(&self, client: C, ctx: &Context) -> Result<CommandResult, Error>
     where
         C: Client + Clone + Send + Sync,
     {
         match self {
             Commands::Lookup(args) => {
                 let lookup: Lookup = Lookup::try_from(args)?;
-                lookup.execute(client).await
+                lookup.execute(client, ctx).await
+            }
+            Commands::Trace(args) => {
+                let trace: Trace = Trace::try_from(args)?;
+                trace.execute(client, ctx).await
             }
             Commands::Ping(args) => {
                 let ping: Ping = Ping::try_from(args)?;
-                ping.execute(client).await
+                ping.execute(client, ctx).await
             }
         }
     }
@@ -63,6 +82,48 @@ pub(crate) struct LookupArgs {
 #[derive(Args)]
 pub(crate) struct PingArgs {}
 
+/// TLS is enabled when the CA certificate is set
+#[derive(Args)]
+pub(crate) struct TlsArgs {
+    /// CA certificate (PEM) used to verify the certificate of the node
+    #[arg(long, value_name = "PATH")]
+    pub(crate) tls_ca: Option<PathBuf>,
+
+    /// Client certificate (PEM), required by nodes using mutual TLS
+    #[arg(long, value_name = "PATH", requires_all = ["tls_ca", "tls_key"])]
+    pub(crate) tls_cert: Option<PathBuf>,
+
+    /// Private key (PEM) of the client certificate
+    #[arg(long, value_name = "PATH", requires_all = ["tls_ca", "tls_cert"])]
+    pub(crate) tls_key: Option<PathBuf>,
+
+    /// DNS name the certificate of the node is issued for
+    #[arg(long, value_name = "NAME", default_value = "localhost")]
+    pub(crate) tls_server_name: String,
+}
+
+impl From<TlsArgs> for ClientConfig {
+    fn from(args: TlsArgs) -> Self {
+        let tls = args.tls_ca.map(|ca_cert| TlsConfig {
+            ca_cert,
+            identity: match (args.tls_cert, args.tls_key) {
+                (Some(cert), Some(key)) => Some(TlsIdentity { cert, key }),
+                _ => None,
+            },
+            client_auth: false,
+            server_name: args.tls_server_name,
+        });
+
+        ClientConfig::with_tls(tls)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Transport {
+    Capnp,
+    Grpc,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub(crate) enum LogLevel {
     Error,
@@ -4,15 +4,15 @@ use chord_rs_core::{Client, NodeId};
 
 use crate::cli::LookupArgs;
 
-use super::{CommandExecute, CommandResult, Error};
+use super::{CommandExecute, CommandResult, Context, Error};
 
 pub(crate) struct Lookup {
-    key: NodeId,
+    pub(crate) key: NodeId,
 }
 
 #[async_trait::async_trait]
 impl CommandExecute for Lookup {
-    async fn execute<C>(&self, client: C) -> Result<CommandResult, Error>
+    async fn execute<C>(&self, client: C, _ctx: &Context) -> Result<CommandResult, Error>
     where
         C: Client + Clone + Send + Sync,
     {
@@ -27,7 +27,7 @@ impl CommandExecute for Lookup {
             result: format!(
                 "Id: {}\nNode:\n  Address: {}\n  Id: {}",
                 self.key,
-                node.addr(),
+                node.address(),
                 node.id()
             ),
             execution: elapsed,
@@ -1,17 +1,28 @@
 use std::{fmt::Display, time::Duration};
 
-use chord_rs_core::{client::ClientError, Client};
+use chord_rs_core::{
+    client::{ClientConfig, ClientError},
+    Client, Node,
+};
 
 pub(crate) mod lookup;
 pub(crate) mod ping;
+pub(crate) mod trace;
 
 #[async_trait::async_trait]
 pub trait CommandExecute {
-    async fn execute<C>(&self, client: C) -> Result<CommandResult, Error>
+    async fn execute<C>(&self, client: C, ctx: &Context) -> Result<CommandResult, Error>
     where
         C: Client + Clone + Send + Sync;
 }
 
+/// The node the CLI connects to and the config of its clients
+pub struct Context {
+    /// Only the address of the node is known, its id isn't necessarily the hash of the address
+    pub(crate) node: Node,
+    pub(crate) config: ClientConfig,
+}
+
 #[derive(Debug)]
 pub struct Error {
     message: String,
@@ -4,13 +4,13 @@ use chord_rs_core::Client;
 
 use crate::cli::PingArgs;
 
-use super::{CommandExecute, CommandResult, Error};
+use super::{CommandExecute, CommandResult, Context, Error};
 
 pub(crate) struct Ping {}
 
 #[async_trait::async_trait]
 impl CommandExecute for Ping {
-    async fn execute<C>(&self, client: C) -> Result<CommandResult, Error>
+    async fn execute<C>(&self, client: C, _ctx: &Context) -> Result<CommandResult, Error>
     where
         C: Client + Clone + Send + Sync,
     {
@@ -0,0 +1,74 @@
+use chord_rs_core::client::ClientsPool;
+use chord_rs_core::lookup::{Hop, Lookup};
+use chord_rs_core::{Client, NodeId};
+
+use crate::cli::LookupArgs;
+
+use super::lookup::LookupError;
+use super::{CommandExecute, CommandResult, Context, Error};
+
+pub(crate) struct Trace {
+    key: NodeId,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Trace {
+    async fn execute<C>(&self, _client: C, ctx: &Context) -> Result<CommandResult, Error>
+    where
+        C: Client + Clone + Send + Sync,
+    {
+        let start = std::time::Instant::now();
+        let clients = ClientsPool::<C>::new(ctx.config.clone());
+        let lookup = Lookup::run(&clients, self.key, ctx.node.clone())
+            .await
+            .map_err(|r| (*r.current_context()).clone())?;
+
+        let elapsed = start.elapsed();
+        let mut result = format!(
+            "Id: {}\n{:<4} {:<20} {:<40} {:<10} Latency\n",
+            self.key, "Hop", "Node id", "Address", "Via"
+        );
+        for (i, hop) in lookup.path.iter().enumerate() {
+            result.push_str(&format!(
+                "{:<4} {:<20} {:<40} {:<10} {:?}\n",
+                i + 1,
+                hop.node.id(),
+                hop.node.address().to_string(),
+                Self::via(i, hop),
+                hop.latency
+            ));
+        }
+        result.push_str(&format!(
+            "Owner:\n  Address: {}\n  Id: {}\nHops: {}",
+            lookup.owner.address(),
+            lookup.owner.id(),
+            lookup.path.len()
+        ));
+
+        Ok(CommandResult {
+            result,
+            execution: elapsed,
+        })
+    }
+}
+
+impl Trace {
+    /// Describe how the lookup got to the hop
+    fn via(i: usize, hop: &Hop) -> String {
+        match hop.finger {
+            Some(finger) => format!("finger {}", finger),
+            None if i == 0 => "start".to_string(),
+            None => "successor".to_string(),
+        }
+    }
+}
+
+impl TryFrom<&LookupArgs> for Trace {
+    type Error = LookupError;
+
+    fn try_from(args: &LookupArgs) -> Result<Self, Self::Error> {
+        let lookup = super::lookup::Lookup::try_from(args)?;
+
+        Ok(Trace { key: lookup.key })
+    }
+}
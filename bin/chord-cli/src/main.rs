@@ -1,7 +1,9 @@
-use chord_rs_core::Client;
-use clap::Parser;
-use commands::{CommandResult, Error};
 use chord_capnp::client::ChordCapnpClient;
+use chord_grpc::client::ChordGrpcClient;
+use chord_rs_core::{Client, Node};
+use clap::Parser;
+use cli::Transport;
+use commands::{CommandResult, Context, Error};
 
 use crate::{cli::Cli, commands::CommandExecute};
 
@@ -19,10 +21,24 @@ async fn main() {
 }
 
 async fn run(cli: Cli) -> Result<CommandResult, Error> {
-    // let client = ChordGrpcClient::init(cli.ring).await;
-    let client = ChordCapnpClient::init(cli.ring).await;
-
-    CommandExecute::execute(&cli.command, client).await
+    let ctx = Context {
+        node: Node::new(cli.ring),
+        config: cli.tls.into(),
+    };
+    match cli.transport {
+        Transport::Capnp => {
+            let client = ChordCapnpClient::init(cli.ring.into(), ctx.config.clone())
+                .await
+                .map_err(|r| (*r.current_context()).clone())?;
+            CommandExecute::execute(&cli.command, client, &ctx).await
+        }
+        Transport::Grpc => {
+            let client = ChordGrpcClient::init(cli.ring.into(), ctx.config.clone())
+                .await
+                .map_err(|r| (*r.current_context()).clone())?;
+            CommandExecute::execute(&cli.command, client, &ctx).await
+        }
+    }
 }
 
 fn print_result(result: CommandResult) {
@@ -1,11 +1,12 @@
-use chord_rs_core::{client::ClientError, Node, NodeId};
-use error_stack::{IntoReport, ResultExt};
+use chord_rs_core::{client::ClientError, lookup::NextHop, Node, NodeId};
+use error_stack::{IntoReport, Report, ResultExt};
 use futures::Future;
+use tokio::time::Instant;
 
 use crate::{
     chord_capnp::{self, chord_node::Client},
     client::CapnpClientError,
-    parser::{ParserError, ResultBuilder},
+    parser::{read_next_hop, ParserError, ResultBuilder},
 };
 
 use super::CmdResult;
@@ -13,10 +14,12 @@ use super::CmdResult;
 #[derive(Debug)]
 pub(crate) enum Command {
     FindSuccessor(NodeId, CmdResult<Node>),
+    NextHop(NodeId, CmdResult<NextHop>),
     Successor(CmdResult<Node>),
     SuccessorList(CmdResult<Vec<Node>>),
     Predecessor(CmdResult<Option<Node>>),
-    Notify(Node, CmdResult<()>),
+    /// The potential predecessor and the token signing it, empty when no secret is set
+    Notify(Node, Vec<u8>, CmdResult<()>),
     Ping(CmdResult<()>),
 }
 
@@ -24,16 +27,17 @@ impl Command {
     pub(crate) fn get_error(&self) -> ClientError {
         match self {
             Command::FindSuccessor(_, _) => ClientError::FindSuccessorFailed,
+            Command::NextHop(_, _) => ClientError::NextHopFailed,
             Command::Successor(_) => ClientError::GetSuccessorFailed,
             Command::SuccessorList(_) => ClientError::GetSuccessorListFailed,
             Command::Predecessor(_) => ClientError::GetPredecessorFailed,
-            Command::Notify(_, _) => ClientError::NotifyFailed,
+            Command::Notify(_, _, _) => ClientError::NotifyFailed,
             Command::Ping(_) => ClientError::PingFailed,
         }
     }
 
-    pub(crate) async fn ping(client: Client, sender: CmdResult<()>) {
-        Self::handle_request(sender, ClientError::PingFailed, || async {
+    pub(crate) async fn ping(client: Client, deadline: Instant, sender: CmdResult<()>) {
+        Self::handle_request(sender, ClientError::PingFailed, deadline, || async {
             let request = client.ping_request();
 
             request.send().promise.await?;
@@ -42,88 +46,161 @@ impl Command {
         .await
     }
 
-    pub(crate) async fn find_successor(client: Client, id: NodeId, sender: CmdResult<Node>) {
-        Self::handle_request(sender, ClientError::FindSuccessorFailed, || async {
-            let mut request = client.find_successor_request();
+    pub(crate) async fn find_successor(
+        client: Client,
+        id: NodeId,
+        deadline: Instant,
+        sender: CmdResult<Node>,
+    ) {
+        Self::handle_request(
+            sender,
+            ClientError::FindSuccessorFailed,
+            deadline,
+            || async {
+                let mut request = client.find_successor_request();
+                request.get().set_id(id.into());
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                request
+                    .get()
+                    .set_timeout_ms(timeout.as_millis().max(1) as u64);
+
+                let reply = request.send().promise.await?;
+                let node = reply.get()?.get_node()?.try_into()?;
+
+                Ok(node)
+            },
+        )
+        .await
+    }
+
+    pub(crate) async fn next_hop(
+        client: Client,
+        id: NodeId,
+        deadline: Instant,
+        sender: CmdResult<NextHop>,
+    ) {
+        Self::handle_request(sender, ClientError::NextHopFailed, deadline, || async {
+            let mut request = client.next_hop_request();
             request.get().set_id(id.into());
 
             let reply = request.send().promise.await?;
-            let node = reply.get()?.get_node()?.try_into()?;
+            let next_hop = read_next_hop(reply.get()?)?;
 
-            Ok(node)
+            Ok(next_hop)
         })
         .await
     }
 
-    pub(crate) async fn get_successor(client: Client, sender: CmdResult<Node>) {
-        Self::handle_request(sender, ClientError::GetSuccessorFailed, || async {
-            let request = client.get_successor_request();
-
-            let reply = request.send().promise.await?;
-            let successor = reply.get()?.get_node()?.try_into()?;
-            Ok(successor)
-        })
+    pub(crate) async fn get_successor(client: Client, deadline: Instant, sender: CmdResult<Node>) {
+        Self::handle_request(
+            sender,
+            ClientError::GetSuccessorFailed,
+            deadline,
+            || async {
+                let request = client.get_successor_request();
+
+                let reply = request.send().promise.await?;
+                let successor = reply.get()?.get_node()?.try_into()?;
+                Ok(successor)
+            },
+        )
         .await;
     }
 
-    pub(crate) async fn get_successor_list(client: Client, sender: CmdResult<Vec<Node>>) {
-        Self::handle_request(sender, ClientError::GetSuccessorListFailed, || async {
-            let request = client.get_successor_list_request();
-
-            let reply = request.send().promise.await?;
-            let nodes = reply.get()?.get_nodes()?;
-            let successors: Vec<Node> = nodes
-                .iter()
-                .map(|node| node.try_into())
-                .collect::<Result<Vec<Node>, ParserError>>()?;
-            Ok(successors)
-        })
+    pub(crate) async fn get_successor_list(
+        client: Client,
+        deadline: Instant,
+        sender: CmdResult<Vec<Node>>,
+    ) {
+        Self::handle_request(
+            sender,
+            ClientError::GetSuccessorListFailed,
+            deadline,
+            || async {
+                let request = client.get_successor_list_request();
+
+                let reply = request.send().promise.await?;
+                let nodes = reply.get()?.get_nodes()?;
+                let successors: Vec<Node> = nodes
+                    .iter()
+                    .map(|node| node.try_into())
+                    .collect::<Result<Vec<Node>, ParserError>>()?;
+                Ok(successors)
+            },
+        )
         .await;
     }
 
-    pub(crate) async fn get_predecessor(client: Client, sender: CmdResult<Option<Node>>) {
-        Self::handle_request(sender, ClientError::GetPredecessorFailed, || async {
-            let request = client.get_predecessor_request();
-
-            let reply = request.send().promise.await?;
-            let node = reply.get()?.get_node()?;
-            match node.which() {
-                Ok(chord_capnp::option::None(())) => Ok(None),
-                Ok(chord_capnp::option::Some(Ok(reader))) => {
-                    let result: Result<Node, ParserError> = reader.try_into();
-                    let node = result?;
-                    Ok(Some(node))
+    pub(crate) async fn get_predecessor(
+        client: Client,
+        deadline: Instant,
+        sender: CmdResult<Option<Node>>,
+    ) {
+        Self::handle_request(
+            sender,
+            ClientError::GetPredecessorFailed,
+            deadline,
+            || async {
+                let request = client.get_predecessor_request();
+
+                let reply = request.send().promise.await?;
+                let node = reply.get()?.get_node()?;
+                match node.which() {
+                    Ok(chord_capnp::option::None(())) => Ok(None),
+                    Ok(chord_capnp::option::Some(Ok(reader))) => {
+                        let result: Result<Node, ParserError> = reader.try_into();
+                        let node = result?;
+                        Ok(Some(node))
+                    }
+                    Ok(chord_capnp::option::Some(Err(err))) => Err(err.into()),
+                    Err(err) => Err(err.into()),
                 }
-                Ok(chord_capnp::option::Some(Err(err))) => Err(err.into()),
-                Err(err) => Err(err.into()),
-            }
-        })
+            },
+        )
         .await
     }
 
-    pub(crate) async fn notify(client: Client, predecessor: Node, sender: CmdResult<()>) {
-        Self::handle_request(sender, ClientError::NotifyFailed, || async {
+    pub(crate) async fn notify(
+        client: Client,
+        predecessor: Node,
+        token: Vec<u8>,
+        deadline: Instant,
+        sender: CmdResult<()>,
+    ) {
+        Self::handle_request(sender, ClientError::NotifyFailed, deadline, || async {
             let mut request = client.notify_request();
             let node = request.get().init_node();
             node.insert(predecessor)?;
+            request.get().set_token(&token);
 
-            let _ = request.send().promise.await;
+            request.send().promise.await?;
             Ok(())
         })
         .await;
     }
 
-    async fn handle_request<F, Res>(sender: CmdResult<Res>, ctx: ClientError, f: impl FnOnce() -> F)
-    where
+    /// Run the request and send its result, the request is cancelled when the deadline passes
+    async fn handle_request<F, Res>(
+        sender: CmdResult<Res>,
+        ctx: ClientError,
+        deadline: Instant,
+        f: impl FnOnce() -> F,
+    ) where
         F: Future<Output = Result<Res, CapnpClientError>>,
         Res: std::fmt::Debug,
     {
-        let result = f()
-            .await
-            .map_err(|err| err.into())
-            .into_report()
-            .attach_printable_lazy(|| ctx);
-
-        sender.send(result).unwrap();
+        let result = match tokio::time::timeout_at(deadline, f()).await {
+            Ok(result) => result
+                .map_err(|err| err.into())
+                .into_report()
+                .attach_printable_lazy(|| ctx.to_string()),
+            Err(_) => Err(
+                Report::new(ClientError::Timeout("Deadline exceeded".to_string()))
+                    .attach_printable(ctx.to_string()),
+            ),
+        };
+
+        // The client stops waiting once the deadline passes
+        let _ = sender.send(result);
     }
 }
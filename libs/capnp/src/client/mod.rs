@@ -1,9 +1,21 @@
-use std::net::SocketAddr;
-
-use chord_rs_core::{client::ClientError, Client, Node, NodeId};
-use error_stack::{IntoReport, Result, ResultExt};
+use std::time::Duration;
+
+use chord_rs_core::{
+    auth::ClusterSecret,
+    client::{ClientConfig, ClientError, Timeouts},
+    deadline,
+    error::ServiceError,
+    lookup::NextHop,
+    Address, Client, Node, NodeId,
+};
+use error_stack::{IntoReport, Report, Result, ResultExt};
 use thiserror::Error;
-use tokio::sync::oneshot::{self, Sender};
+use tokio::{
+    sync::oneshot::{self, Sender},
+    time::Instant,
+};
+
+use crate::tls::ClientTls;
 
 use self::{command::Command, spawner::LocalSpawner};
 
@@ -15,56 +27,119 @@ type CmdResult<T> = oneshot::Sender<Result<T, ClientError>>;
 #[derive(Clone)]
 pub struct ChordCapnpClient {
     spawner: LocalSpawner,
+    /// Secret used to sign the notify calls
+    secret: Option<ClusterSecret>,
+    timeouts: Timeouts,
 }
 
 #[async_trait::async_trait]
 impl Client for ChordCapnpClient {
-    async fn init(addr: SocketAddr) -> Self {
-        let spawner = LocalSpawner::new(addr);
-
-        Self { spawner }
+    async fn init(addr: Address, config: ClientConfig) -> Result<Self, ClientError> {
+        let tls = config
+            .tls
+            .as_ref()
+            .map(ClientTls::new)
+            .transpose()
+            .change_context_lazy(|| {
+                ClientError::InvalidConfig(format!("Failed to set up TLS for {}", addr))
+            })?;
+        let spawner = LocalSpawner::new(addr, tls)?;
+
+        Ok(Self {
+            spawner,
+            secret: config.secret,
+            timeouts: config.timeouts,
+        })
     }
 
     async fn find_successor(&self, id: NodeId) -> Result<Node, ClientError> {
-        self.handle_request(|tx| Command::FindSuccessor(id, tx))
+        self.handle_request(self.timeouts.find_successor, |tx| {
+            Command::FindSuccessor(id, tx)
+        })
+        .await
+    }
+
+    async fn next_hop(&self, id: NodeId) -> Result<NextHop, ClientError> {
+        self.handle_request(self.timeouts.next_hop, |tx| Command::NextHop(id, tx))
             .await
     }
 
     async fn successor(&self) -> Result<Node, ClientError> {
-        self.handle_request(|tx| Command::Successor(tx)).await
+        self.handle_request(self.timeouts.successor, |tx| Command::Successor(tx))
+            .await
     }
 
     async fn successor_list(&self) -> Result<Vec<Node>, ClientError> {
-        self.handle_request(|tx| Command::SuccessorList(tx)).await
+        self.handle_request(self.timeouts.successor_list, |tx| {
+            Command::SuccessorList(tx)
+        })
+        .await
     }
 
     async fn predecessor(&self) -> Result<Option<Node>, ClientError> {
-        self.handle_request(|tx| Command::Predecessor(tx)).await
+        self.handle_request(self.timeouts.predecessor, |tx| Command::Predecessor(tx))
+            .await
     }
 
     async fn notify(&self, predecessor: Node) -> Result<(), ClientError> {
-        self.handle_request(|tx| Command::Notify(predecessor, tx))
-            .await
+        let token = self
+            .secret
+            .as_ref()
+            .map(|secret| secret.sign(&predecessor))
+            .unwrap_or_default();
+        self.handle_request(self.timeouts.notify, |tx| {
+            Command::Notify(predecessor, token, tx)
+        })
+        .await
     }
 
     async fn ping(&self) -> Result<(), ClientError> {
-        self.handle_request(|tx| Command::Ping(tx)).await
+        self.handle_request(self.timeouts.ping, |tx| Command::Ping(tx))
+            .await
     }
 }
 
 impl ChordCapnpClient {
+    /// Send the command to the spawner and wait for its result
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The timeout of the call, capped by the deadline of the current task
+    /// * `request` - Creates the command from the sender of its result
     async fn handle_request<T>(
         &self,
+        timeout: Duration,
         request: impl FnOnce(Sender<Result<T, ClientError>>) -> Command,
     ) -> Result<T, ClientError> {
+        let spawner = &self.spawner;
+        let timeout = deadline::budget(timeout);
+        let deadline = Instant::now() + timeout;
         let (tx, rx) = oneshot::channel();
-        self.spawner.spawn(request(tx)).await.unwrap()?;
-
-        let result = rx
-            .await
-            .into_report()
-            .change_context(ClientError::Unexpected)?;
-        result
+        let command = request(tx);
+        let context = command.get_error();
+
+        // The spawner enforces the deadline too, this covers the time the command is queued
+        let result = tokio::time::timeout_at(deadline, async {
+            // The senders are dropped without a result when the thread of the spawner stops
+            spawner
+                .spawn(command, deadline)?
+                .await
+                .into_report()
+                .change_context_lazy(spawner::shut_down)??;
+
+            rx.await
+                .into_report()
+                .change_context_lazy(spawner::shut_down)?
+        })
+        .await;
+
+        result.unwrap_or_else(|_| {
+            Err(Report::new(ClientError::Timeout(format!(
+                "No response in {:?}",
+                timeout
+            )))
+            .attach_printable(context.to_string()))
+        })
     }
 }
 
@@ -74,6 +149,11 @@ pub(crate) enum CapnpClientError {
     InvalidRequest(String),
     #[error("Connection failed: {0}")]
     ConnectionFailed(String),
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+    /// Error returned by the node, see [`crate::parser::encode_error`]
+    #[error("{0}: {1}")]
+    Service(ServiceError, String),
     #[error("Unexpected error: {0}")]
     Unexpected(String),
 }
@@ -1,129 +1,374 @@
-use std::net::SocketAddr;
+use std::{cell::Cell, rc::Rc, time::Duration};
 
-use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
-use chord_rs_core::client::ClientError;
+use capnp_rpc::{rpc_twoparty_capnp, twoparty, Disconnector, RpcSystem};
+use chord_rs_core::{client::ClientError, Address};
 use error_stack::{IntoReport, Report, ResultExt};
 use futures::AsyncReadExt;
 use thiserror::Error;
 use tokio::{
+    io::{AsyncRead, AsyncWrite},
     runtime::Builder,
-    sync::{mpsc, oneshot},
+    sync::{mpsc, oneshot, Notify},
     task::LocalSet,
+    time::Instant,
 };
 
-use crate::chord_capnp;
+use crate::{chord_capnp, tls::ClientTls};
 
-use super::command::Command;
+/// Connections which aren't used for this long are closed
+pub(crate) const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
 
+type Request = (
+    super::Command,
+    Instant,
+    oneshot::Sender<Result<(), Report<ClientError>>>,
+);
+
+/// Runs the commands of a client on a dedicated thread
+///
+/// Cap'n'proto RPC isn't `Send`, so the connection lives on a `LocalSet`. The thread keeps
+/// a single connection to the node, which is shared by all the commands. It's established
+/// on the first command, re-established when the node drops it and closed when it's idle.
 #[derive(Clone)]
 pub(crate) struct LocalSpawner {
-    sender: mpsc::UnboundedSender<(
-        super::Command,
-        oneshot::Sender<Result<(), Report<ClientError>>>,
-    )>,
+    sender: mpsc::UnboundedSender<Request>,
 }
 
 impl LocalSpawner {
-    pub fn new(addr: SocketAddr) -> Self {
-        let (sender, mut receiver) =
-            mpsc::unbounded_channel::<(Command, oneshot::Sender<Result<(), Report<ClientError>>>)>(
-            );
-        let rt = Builder::new_current_thread().enable_all().build().unwrap();
+    pub fn new(addr: Address, tls: Option<ClientTls>) -> Result<Self, Report<ClientError>> {
+        Self::with_idle_timeout(addr, tls, IDLE_TIMEOUT)
+    }
+
+    /// Create a spawner, which closes the connection after it isn't used for `idle_timeout`
+    pub(crate) fn with_idle_timeout(
+        addr: Address,
+        tls: Option<ClientTls>,
+        idle_timeout: Duration,
+    ) -> Result<Self, Report<ClientError>> {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Request>();
+        let rt = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .into_report()
+            .change_context(ClientError::Unexpected)
+            .attach_printable("Failed to create the runtime of the client")?;
 
         std::thread::spawn(move || {
             let local = LocalSet::new();
 
             local.spawn_local(async move {
-                while let Some((command, result_sender)) = receiver.recv().await {
-                    let context = command.get_error();
-                    if let Err(report) = Self::run_local(addr, command).await {
-                        match report.current_context() {
-                            SpawnerError::ClientConnectionError => {
-                                log::debug!("{report:?}");
-                            }
-                            _ => {
-                                log::error!("Error when handling a request: {report:?}");
-                            }
-                        }
-                        let _ = result_sender.send(Err(report.change_context(context)));
-                    } else {
-                        let _ = result_sender.send(Ok(()));
+                let mut connection = Connection::new(addr.clone(), tls);
+                loop {
+                    let event = tokio::select! {
+                        request = receiver.recv() => Event::Request(request),
+                        _ = connection.idle(idle_timeout) => Event::Idle,
                     };
+
+                    match event {
+                        Event::Request(Some((command, deadline, result_sender))) => {
+                            let result = Self::run_local(&mut connection, command, deadline).await;
+                            let _ = result_sender.send(result);
+                        }
+                        Event::Request(None) => break,
+                        Event::Idle => {
+                            log::trace!("Closing idle connection to {}", addr);
+                            connection.close().await;
+                        }
+                    }
                 }
+
+                // All the clients are gone
+                connection.close().await;
             });
 
             rt.block_on(local);
         });
 
-        Self { sender }
+        Ok(Self { sender })
     }
 
+    /// Run the command on the thread of the spawner
+    ///
+    /// Fails with [`ClientError::ConnectionFailed`] when the thread has shut down, so that the
+    /// client is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `task` - The command to run
+    /// * `deadline` - The command fails with a timeout when it's not done by then
     pub(crate) fn spawn(
         &self,
         task: super::Command,
-    ) -> oneshot::Receiver<Result<(), Report<ClientError>>> {
+        deadline: Instant,
+    ) -> Result<oneshot::Receiver<Result<(), Report<ClientError>>>, Report<ClientError>> {
         let (tx, rx) = oneshot::channel();
         self.sender
-            .send((task, tx))
-            .expect("Thread with LocalSet has shut down.");
+            .send((task, deadline, tx))
+            .map_err(|_| Report::new(shut_down()))?;
 
-        rx
+        Ok(rx)
     }
 
-    async fn rpc_system(
-        addr: SocketAddr,
-    ) -> Result<RpcSystem<rpc_twoparty_capnp::Side>, SpawnerError> {
-        let stream = tokio::net::TcpStream::connect(&addr).await?;
+    /// Run the command on the connection, connecting first when needed
+    ///
+    /// The command runs in its own task, so commands are multiplexed on the connection.
+    /// Only the connection errors are returned, the command sends its own result.
+    async fn run_local(
+        connection: &mut Connection,
+        command: super::Command,
+        deadline: Instant,
+    ) -> Result<(), Report<ClientError>> {
+        let addr = connection.addr.clone();
+        let Ok(client) = tokio::time::timeout_at(deadline, connection.client()).await else {
+            let error = ClientError::Timeout(format!("Failed to connect to {} in time", addr));
+            return Err(Report::new(error).attach_printable(command.get_error().to_string()));
+        };
+        let (client, guard) = match client {
+            Ok(client) => client,
+            Err(report) => {
+                let context = command.get_error();
+                let report = match report.current_context() {
+                    SpawnerError::ClientConnectionError => {
+                        log::debug!("{report:?}");
+                        let error =
+                            ClientError::ConnectionFailed(format!("Failed to connect to {}", addr));
+                        report
+                            .change_context(error)
+                            .attach_printable(context.to_string())
+                    }
+                    _ => {
+                        log::error!("Error when handling a request: {report:?}");
+                        report.change_context(context)
+                    }
+                };
+                return Err(report);
+            }
+        };
 
-        stream.set_nodelay(true)?;
-        let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
-        let rpc_network = Box::new(twoparty::VatNetwork::new(
-            reader,
-            writer,
-            rpc_twoparty_capnp::Side::Client,
-            Default::default(),
-        ));
+        tokio::task::spawn_local(async move {
+            match command {
+                super::command::Command::FindSuccessor(node_id, resp) => {
+                    super::Command::find_successor(client, node_id, deadline, resp).await
+                }
+                super::command::Command::NextHop(id, resp) => {
+                    super::Command::next_hop(client, id, deadline, resp).await
+                }
+                super::command::Command::Predecessor(resp) => {
+                    super::Command::get_predecessor(client, deadline, resp).await
+                }
+                super::command::Command::Notify(node, token, resp) => {
+                    super::Command::notify(client, node, token, deadline, resp).await
+                }
+                super::command::Command::Successor(resp) => {
+                    super::Command::get_successor(client, deadline, resp).await
+                }
+                super::command::Command::SuccessorList(resp) => {
+                    super::Command::get_successor_list(client, deadline, resp).await
+                }
+                super::Command::Ping(resp) => super::Command::ping(client, deadline, resp).await,
+            }
+            drop(guard);
+        });
+
+        Ok(())
+    }
+}
+
+enum Event {
+    Request(Option<Request>),
+    Idle,
+}
+
+/// The connection to a node, established on demand
+struct Connection {
+    /// A host name is resolved on every connect
+    addr: Address,
+    tls: Option<ClientTls>,
+    active: Option<ActiveConnection>,
+}
+
+struct ActiveConnection {
+    client: chord_capnp::chord_node::Client,
+    disconnector: Disconnector<rpc_twoparty_capnp::Side>,
+    /// Cleared when the RPC system stops, e.g. when the node closes the connection
+    alive: Rc<Cell<bool>>,
+    usage: Rc<Usage>,
+}
+
+/// Tracks the commands running on a connection
+#[derive(Default)]
+struct Usage {
+    in_flight: Cell<usize>,
+    last_used: Cell<Option<Instant>>,
+    /// Notified when the last running command is done
+    done: Notify,
+}
+
+/// Marks a command as running until it's dropped
+struct UsageGuard(Rc<Usage>);
 
-        return Ok(RpcSystem::new(rpc_network, None));
+impl UsageGuard {
+    fn new(usage: Rc<Usage>) -> Self {
+        usage.in_flight.set(usage.in_flight.get() + 1);
+        Self(usage)
     }
+}
 
-    async fn run_local(
-        addr: SocketAddr,
-        command: super::Command,
-    ) -> Result<(), Report<SpawnerError>> {
-        let mut rpc_system = Self::rpc_system(addr)
+impl Drop for UsageGuard {
+    fn drop(&mut self) {
+        let in_flight = self.0.in_flight.get() - 1;
+        self.0.in_flight.set(in_flight);
+        self.0.last_used.set(Some(Instant::now()));
+        if in_flight == 0 {
+            self.0.done.notify_one();
+        }
+    }
+}
+
+impl Connection {
+    fn new(addr: Address, tls: Option<ClientTls>) -> Self {
+        Self {
+            addr,
+            tls,
+            active: None,
+        }
+    }
+
+    /// Get a client for the connection, reconnecting when it was lost
+    async fn client(
+        &mut self,
+    ) -> Result<(chord_capnp::chord_node::Client, UsageGuard), Report<SpawnerError>> {
+        if let Some(active) = &self.active {
+            if !active.alive.get() {
+                log::debug!("Connection to {} was lost, reconnecting", self.addr);
+                self.active = None;
+            }
+        }
+
+        let active = match &mut self.active {
+            Some(active) => active,
+            None => self.active.insert(self.connect().await?),
+        };
+
+        let guard = UsageGuard::new(active.usage.clone());
+        Ok((active.client.clone(), guard))
+    }
+
+    async fn connect(&self) -> Result<ActiveConnection, Report<SpawnerError>> {
+        let mut rpc_system = Self::rpc_system(&self.addr, self.tls.as_ref())
             .await
             .into_report()
-            .attach_printable_lazy(|| format!("Client address: {:?}", addr))?;
+            .attach_printable_lazy(|| format!("Client address: {}", self.addr))?;
         let client: chord_capnp::chord_node::Client =
             rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
         let disconnector = rpc_system.get_disconnector();
-        tokio::task::spawn_local(rpc_system);
 
-        match command {
-            super::command::Command::FindSuccessor(node_id, resp) => {
-                super::Command::find_successor(client, node_id, resp).await
-            }
-            super::command::Command::Predecessor(resp) => {
-                super::Command::get_predecessor(client, resp).await
+        let alive = Rc::new(Cell::new(true));
+        let addr = self.addr.clone();
+        let rpc_alive = alive.clone();
+        tokio::task::spawn_local(async move {
+            if let Err(err) = rpc_system.await {
+                log::debug!("Connection to {} closed: {}", addr, err);
             }
-            super::command::Command::Notify(node, resp) => {
-                super::Command::notify(client, node, resp).await
+            rpc_alive.set(false);
+        });
+        log::trace!("Connected to {}", self.addr);
+
+        Ok(ActiveConnection {
+            client,
+            disconnector,
+            alive,
+            usage: Rc::default(),
+        })
+    }
+
+    /// Resolves when no command used the connection for `timeout`, never without a connection
+    async fn idle(&self, timeout: Duration) {
+        let usage = match &self.active {
+            Some(active) => active.usage.clone(),
+            None => return std::future::pending().await,
+        };
+
+        loop {
+            // The idle time starts when the last running command is done
+            if usage.in_flight.get() > 0 {
+                usage.done.notified().await;
+                continue;
             }
-            super::command::Command::Successor(resp) => {
-                super::Command::get_successor(client, resp).await
+
+            let deadline = usage.last_used.get().unwrap_or_else(Instant::now) + timeout;
+            tokio::time::sleep_until(deadline.into()).await;
+
+            let expired = usage
+                .last_used
+                .get()
+                .map_or(true, |last_used| last_used + timeout <= Instant::now());
+            if usage.in_flight.get() == 0 && expired {
+                return;
             }
-            super::command::Command::SuccessorList(resp) => {
-                super::Command::get_successor_list(client, resp).await
+        }
+    }
+
+    async fn close(&mut self) {
+        if let Some(active) = self.active.take() {
+            if active.alive.get() {
+                if let Err(err) = active.disconnector.await {
+                    log::debug!("Error disconnecting: {:?}", err);
+                }
             }
-            super::Command::Ping(resp) => super::Command::ping(client, resp).await,
         }
+    }
+
+    async fn rpc_system(
+        addr: &Address,
+        tls: Option<&ClientTls>,
+    ) -> Result<RpcSystem<rpc_twoparty_capnp::Side>, SpawnerError> {
+        let stream = match addr {
+            Address::Socket(addr) => tokio::net::TcpStream::connect(addr).await?,
+            Address::Host(host, port) => {
+                // A host name which doesn't resolve is as unreachable as a node which is down
+                let addrs: Vec<_> = tokio::net::lookup_host((host.as_str(), *port))
+                    .await
+                    .map_err(|err| {
+                        log::debug!("Failed to resolve {}: {}", host, err);
+                        SpawnerError::ClientConnectionError
+                    })?
+                    .collect();
+                if addrs.is_empty() {
+                    log::debug!("Host {} has no addresses", host);
+                    return Err(SpawnerError::ClientConnectionError);
+                }
+                tokio::net::TcpStream::connect(addrs.as_slice()).await?
+            }
+        };
 
-        if let Err(err) = disconnector.await {
-            log::error!("Error disconnecting: {:?}", err);
+        stream.set_nodelay(true)?;
+        match tls {
+            Some(tls) => {
+                let stream = tls
+                    .connector
+                    .connect(tls.server_name.clone(), stream)
+                    .await
+                    .map_err(SpawnerError::Tls)?;
+                Ok(Self::rpc_network(stream))
+            }
+            None => Ok(Self::rpc_network(stream)),
         }
+    }
 
-        Ok(())
+    fn rpc_network<S>(stream: S) -> RpcSystem<rpc_twoparty_capnp::Side>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + 'static,
+    {
+        let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+        let rpc_network = Box::new(twoparty::VatNetwork::new(
+            reader,
+            writer,
+            rpc_twoparty_capnp::Side::Client,
+            Default::default(),
+        ));
+
+        RpcSystem::new(rpc_network, None)
     }
 }
 
@@ -132,6 +377,9 @@ pub(crate) enum SpawnerError {
     #[error("Failed to connect to client")]
     ClientConnectionError,
 
+    #[error("TLS handshake failed: {0}")]
+    Tls(std::io::Error),
+
     #[error("Other error: {0:?}")]
     Other(std::io::Error),
 }
@@ -144,6 +392,8 @@ impl From<std::io::Error> for SpawnerError {
             | std::io::ErrorKind::ConnectionAborted
             | std::io::ErrorKind::NotConnected
             | std::io::ErrorKind::AddrNotAvailable
+            | std::io::ErrorKind::HostUnreachable
+            | std::io::ErrorKind::NetworkUnreachable
             | std::io::ErrorKind::BrokenPipe
             | std::io::ErrorKind::TimedOut
             | std::io::ErrorKind::Interrupted => Self::ClientConnectionError,
@@ -151,3 +401,163 @@ impl From<std::io::Error> for SpawnerError {
         }
     }
 }
+
+/// The error of the commands which were sent to a spawner whose thread has shut down
+pub(crate) fn shut_down() -> ClientError {
+    ClientError::ConnectionFailed("The thread of the client has shut down".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use chord_rs_core::{
+        client::Timeouts,
+        testing::{free_addr, wait_for_listener},
+        Client, NodeService,
+    };
+
+    use super::*;
+    use crate::{client::ChordCapnpClient, server::NodeServerImpl};
+
+    /// Server counting the connections it accepted, which drops them on request
+    struct TestServer {
+        addr: SocketAddr,
+        accepted: Arc<AtomicUsize>,
+        drop_connections: mpsc::UnboundedSender<()>,
+    }
+
+    impl TestServer {
+        async fn start() -> Self {
+            let addr = free_addr();
+            let accepted = Arc::new(AtomicUsize::new(0));
+            let (drop_connections, mut drop_rx) = mpsc::unbounded_channel::<()>();
+
+            let counter = accepted.clone();
+            std::thread::spawn(move || {
+                let rt = Builder::new_current_thread().enable_all().build().unwrap();
+                let local = LocalSet::new();
+                local.block_on(&rt, async move {
+                    let node = Arc::new(NodeService::<ChordCapnpClient>::new(addr, 3));
+                    let client: chord_capnp::chord_node::Client =
+                        capnp_rpc::new_client(NodeServerImpl::new(node));
+                    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+                    let mut connections = Vec::new();
+                    loop {
+                        tokio::select! {
+                            accepted = listener.accept() => {
+                                let (stream, _) = accepted.unwrap();
+                                counter.fetch_add(1, Ordering::SeqCst);
+                                let rpc_system = crate::rpc_system(stream, client.clone().client);
+                                connections.push(tokio::task::spawn_local(rpc_system));
+                            }
+                            _ = drop_rx.recv() => {
+                                connections.drain(..).for_each(|connection| connection.abort());
+                            }
+                        }
+                    }
+                });
+            });
+            wait_for_listener(addr).await;
+
+            Self {
+                addr,
+                accepted,
+                drop_connections,
+            }
+        }
+
+        fn client(&self, idle_timeout: Duration) -> ChordCapnpClient {
+            ChordCapnpClient {
+                spawner: LocalSpawner::with_idle_timeout(self.addr.into(), None, idle_timeout)
+                    .unwrap(),
+                secret: None,
+                timeouts: Timeouts::default(),
+            }
+        }
+
+        /// Number of connections accepted, excluding the probe of `wait_for_listener`
+        fn connections(&self) -> usize {
+            self.accepted.load(Ordering::SeqCst) - 1
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn commands_share_a_connection() {
+        let server = TestServer::start().await;
+        let client = server.client(IDLE_TIMEOUT);
+
+        for _ in 0..5 {
+            client.ping().await.unwrap();
+        }
+        let (a, b) = tokio::join!(client.ping(), client.successor());
+        a.unwrap();
+        b.unwrap();
+
+        assert_eq!(1, server.connections());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reconnects_when_the_connection_is_lost() {
+        let server = TestServer::start().await;
+        let client = server.client(IDLE_TIMEOUT);
+        client.ping().await.unwrap();
+
+        server.drop_connections.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        client.ping().await.unwrap();
+        assert_eq!(2, server.connections());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn idle_connections_are_closed() {
+        let server = TestServer::start().await;
+        let client = server.client(Duration::from_millis(100));
+        client.ping().await.unwrap();
+        client.ping().await.unwrap();
+        assert_eq!(1, server.connections());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        client.ping().await.unwrap();
+        assert_eq!(2, server.connections());
+    }
+
+    #[tokio::test]
+    async fn commands_fail_to_connect_when_the_thread_has_shut_down() {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        drop(receiver);
+        let client = ChordCapnpClient {
+            spawner: LocalSpawner { sender },
+            secret: None,
+            timeouts: Timeouts::default(),
+        };
+
+        let report = client.ping().await.unwrap_err();
+        assert!(matches!(
+            report.current_context(),
+            ClientError::ConnectionFailed(_)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unresolvable_hosts_fail_to_connect() {
+        let client = ChordCapnpClient {
+            spawner: LocalSpawner::new(Address::Host("node.invalid".to_string(), 42000), None)
+                .unwrap(),
+            secret: None,
+            timeouts: Timeouts::default(),
+        };
+
+        let report = client.ping().await.unwrap_err();
+        assert!(matches!(
+            report.current_context(),
+            ClientError::ConnectionFailed(_)
+        ));
+    }
+}
@@ -1,80 +1,496 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
-use chord_rs_core::NodeService;
+use chord_rs_core::{
+    client::TlsConfig, error::ServiceError, server::Seed, Client, NodeService, ServiceConfig,
+};
 use client::ChordCapnpClient;
+use error_stack::{IntoReport, Result, ResultExt};
 use futures::AsyncReadExt;
-use tokio::sync::Semaphore;
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::Semaphore,
+};
+use worker::{Connection, Worker, Workers};
 
 pub mod client;
 pub mod parser;
 mod server;
+pub mod tls;
+mod worker;
 
 pub mod chord_capnp {
 
     include!(concat!(env!("OUT_DIR"), "/capnp/chord_capnp.rs"));
 }
 
-pub struct Server {
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("Failed to load the TLS config")]
+    Tls,
+    #[error("Failed to bind {0}")]
+    Bind(SocketAddr),
+}
+
+pub struct Server<C: Client = ChordCapnpClient> {
     addr: SocketAddr,
-    node: Arc<NodeService<ChordCapnpClient>>,
+    node: Arc<NodeService<C>>,
+    tls: Option<TlsConfig>,
+    workers: usize,
+    accept_queue: AcceptQueue,
 }
 
 impl Server {
-    pub async fn new(addr: SocketAddr, ring: Option<SocketAddr>) -> Self {
+    /// Create a new server and join the ring
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to listen on
+    /// * `ring` - The nodes of the ring to join through, tried in turn.
+    ///   The node starts a new ring when there are none
+    /// * `config` - The config of the node, or just the config of its clients.
+    ///   The TLS configuration of the clients is also used by the server
+    ///
+    /// # Errors
+    ///
+    /// Fails when the node can't join the ring, see [`chord_rs_core::server::join_ring`]
+    pub async fn new(
+        addr: SocketAddr,
+        ring: Vec<Seed>,
+        config: impl Into<ServiceConfig>,
+    ) -> Result<Self, ServiceError> {
         const REPLICATION_FACTOR: usize = 3; // TODO: make this configurable
-        let node_service = Arc::new(NodeService::new(addr, REPLICATION_FACTOR));
-        if let Some(ring) = ring {
-            const MAX_RETRIES: u32 = 5;
-            chord_rs_core::server::join_ring(node_service.clone(), ring, MAX_RETRIES).await;
+        let config = config.into();
+        let tls = config.client.tls.clone();
+        let discovery = config.discovery.clone();
+        let node_service = Arc::new(NodeService::with_config(addr, REPLICATION_FACTOR, config));
+        chord_rs_core::server::join_ring(node_service.clone(), ring).await?;
+        if let Some(discovery) = discovery {
+            chord_rs_core::discovery::spawn(node_service.clone(), discovery);
         }
         chord_rs_core::server::background_tasks(node_service.clone());
 
-        Self {
+        Ok(Self {
             addr,
             node: node_service,
+            tls,
+            workers: 1,
+            accept_queue: AcceptQueue::default(),
+        })
+    }
+}
+
+impl<C: Client + Clone + Sync + Send + 'static> Server<C> {
+    /// Create a server for an already existing node service.
+    ///
+    /// Joining the ring and running the background tasks is up to the caller,
+    /// which allows the same node service to be exposed by multiple servers.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to listen on
+    /// * `node` - The node service to expose
+    /// * `tls` - TLS configuration of the server, plaintext TCP is used when it's not set
+    pub fn with_service(
+        addr: SocketAddr,
+        node: Arc<NodeService<C>>,
+        tls: Option<TlsConfig>,
+    ) -> Self {
+        Self {
+            addr,
+            node,
+            tls,
+            workers: 1,
+            accept_queue: AcceptQueue::default(),
         }
     }
 
-    pub async fn run(&self, max_connections: usize) {
-        tokio::task::LocalSet::new()
-            .run_until(async move {
-                let server = server::NodeServerImpl::new(self.node.clone());
-                let listener = tokio::net::TcpListener::bind(&self.addr).await.unwrap();
-                let chord_node_client: chord_capnp::chord_node::Client =
-                    capnp_rpc::new_client(server);
-                let sem = Arc::new(Semaphore::new(max_connections));
-
-                loop {
-                    let (stream, _) = listener.accept().await.unwrap();
-                    let sem = sem.clone();
-                    stream.set_nodelay(true).unwrap();
-                    let (reader, writer) =
-                        tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
-                    let network = twoparty::VatNetwork::new(
-                        reader,
-                        writer,
-                        rpc_twoparty_capnp::Side::Server,
-                        Default::default(),
-                    );
-
-                    let rpc_system =
-                        RpcSystem::new(Box::new(network), Some(chord_node_client.clone().client));
-
-                    tokio::task::spawn_local(async move {
-                        if let Ok(aq) = sem.try_acquire() {
-                            log::trace!("Semaphore acquired");
-                            if let Err(err) = rpc_system.await {
-                                log::error!("rpc system error: {}", err);
-                            }
-                            log::trace!("Semaphore released");
-                            drop(aq);
-                        } else {
-                            log::debug!("Failed to acquire semaphore")
-                        }
-                    });
-                }
-            })
+    /// Serve the connections on the given number of worker threads
+    ///
+    /// # Arguments
+    ///
+    /// * `workers` - The number of threads, at least one
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Queue the connections over `max_connections` for up to `timeout`
+    ///
+    /// Connections which don't get a slot in time, or don't fit in the queue, are
+    /// rejected with an overload error.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum number of connections waiting for a slot
+    /// * `timeout` - How long a connection waits for a slot
+    pub fn accept_queue(mut self, size: usize, timeout: Duration) -> Self {
+        self.accept_queue = AcceptQueue { size, timeout };
+        self
+    }
+
+    /// Accept connections and spread them across the workers
+    ///
+    /// # Arguments
+    ///
+    /// * `max_connections` - The maximum number of connections served at the same time
+    ///
+    /// # Errors
+    ///
+    /// Fails when the TLS config can't be loaded or the address can't be bound,
+    /// the errors of the accepted connections are logged.
+    pub async fn run(&self, max_connections: usize) -> Result<(), ServerError> {
+        let acceptor = self
+            .tls
+            .as_ref()
+            .map(tls::acceptor)
+            .transpose()
+            .change_context(ServerError::Tls)?;
+
+        let workers = Arc::new(Workers::new(
+            (0..self.workers)
+                .map(|id| Worker::spawn(id, self.node.clone(), acceptor.clone()))
+                .collect(),
+        ));
+        let listener = tokio::net::TcpListener::bind(&self.addr)
             .await
+            .into_report()
+            .change_context(ServerError::Bind(self.addr))?;
+        let sem = Arc::new(Semaphore::new(max_connections));
+        let queue = Arc::new(Semaphore::new(self.accept_queue.size));
+        log::info!(
+            "Serving capnp on {} with {} workers",
+            self.addr,
+            workers.len()
+        );
+
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    log::error!("Failed to accept a connection: {}", err);
+                    continue;
+                }
+            };
+            if let Err(err) = stream.set_nodelay(true) {
+                log::warn!("Failed to set TCP_NODELAY for {}: {}", peer, err);
+            }
+            let stream = match stream.into_std() {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::error!("Failed to hand over the connection: {}", err);
+                    continue;
+                }
+            };
+
+            if let Ok(permit) = sem.clone().try_acquire_owned() {
+                log::trace!("Semaphore acquired");
+                workers.send(Connection::Accepted { stream, permit });
+                continue;
+            }
+
+            let Ok(slot) = queue.clone().try_acquire_owned() else {
+                log::warn!("Accept queue is full, rejecting connection from {}", peer);
+                workers.send(Connection::Rejected(stream));
+                continue;
+            };
+
+            let sem = sem.clone();
+            let workers = workers.clone();
+            let timeout = self.accept_queue.timeout;
+            tokio::spawn(async move {
+                let connection = match tokio::time::timeout(timeout, sem.acquire_owned()).await {
+                    Ok(Ok(permit)) => {
+                        log::trace!("Semaphore acquired");
+                        Connection::Accepted { stream, permit }
+                    }
+                    _ => {
+                        log::warn!(
+                            "No connection slot freed in {:?}, rejecting {}",
+                            timeout,
+                            peer
+                        );
+                        Connection::Rejected(stream)
+                    }
+                };
+                drop(slot);
+                workers.send(connection);
+            });
+        }
+    }
+}
+
+/// Connections waiting for a slot when the server is at `max_connections`
+struct AcceptQueue {
+    size: usize,
+    timeout: Duration,
+}
+
+impl Default for AcceptQueue {
+    fn default() -> Self {
+        Self {
+            size: 128,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+fn rpc_system<S>(
+    stream: S,
+    bootstrap: capnp::capability::Client,
+) -> RpcSystem<rpc_twoparty_capnp::Side>
+where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    let (reader, writer) = tokio_util::compat::TokioAsyncReadCompatExt::compat(stream).split();
+    let network = twoparty::VatNetwork::new(
+        reader,
+        writer,
+        rpc_twoparty_capnp::Side::Server,
+        Default::default(),
+    );
+
+    RpcSystem::new(Box::new(network), Some(bootstrap))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chord_rs_core::client::ClientConfig;
+
+    fn serve(addr: SocketAddr, node: Arc<NodeService<ChordCapnpClient>>) {
+        serve_with_tls(addr, node, None);
+    }
+
+    fn serve_with_tls<C: Client + Clone + Sync + Send + 'static>(
+        addr: SocketAddr,
+        node: Arc<NodeService<C>>,
+        tls: Option<TlsConfig>,
+    ) {
+        // The server runs on a LocalSet, so it gets a thread with its own runtime
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(Server::with_service(addr, node, tls).workers(2).run(64))
+                .expect("Failed to run the server");
+        });
+    }
+
+    chord_rs_core::client_conformance_tests!(ChordCapnpClient, serve);
+
+    mod dyn_client {
+        use chord_rs_core::client::{DynClient, InitConnector};
+
+        use super::*;
+
+        fn serve(addr: SocketAddr, node: Arc<NodeService<DynClient>>) {
+            serve_with_tls(addr, node, None);
+        }
+
+        chord_rs_core::client_conformance_tests!(
+            DynClient,
+            serve,
+            ClientConfig::default()
+                .connector(Some(Arc::new(InitConnector::<ChordCapnpClient>::new())))
+        );
+    }
+
+    mod tls {
+        use chord_rs_core::testing::tls::{assert_rejected, TlsFixture};
+        use chord_rs_core::testing::{free_addr, wait_for, wait_for_listener};
+
+        use super::*;
+
+        chord_rs_core::client_conformance_tests!(ChordCapnpClient, tls |tls: TlsConfig| {
+            move |addr, node| serve_with_tls(addr, node, Some(tls.clone()))
+        });
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn untrusted_clients_are_rejected() {
+            let fixture = TlsFixture::generate();
+            let addr = free_addr();
+            serve_with_tls(
+                addr,
+                Arc::new(NodeService::<ChordCapnpClient>::new(addr, 3)),
+                Some(fixture.node_config(true)),
+            );
+            wait_for::<ChordCapnpClient>(addr, fixture.client_config()).await;
+
+            assert_rejected::<ChordCapnpClient>(addr, ClientConfig::default()).await;
+            assert_rejected::<ChordCapnpClient>(addr, fixture.anonymous_client_config()).await;
+            assert_rejected::<ChordCapnpClient>(addr, fixture.rogue_client_config()).await;
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn untrusted_servers_are_rejected() {
+            let fixture = TlsFixture::generate();
+            let addr = free_addr();
+            let tls = fixture.rogue_node_config();
+            serve_with_tls::<ChordCapnpClient>(
+                addr,
+                Arc::new(NodeService::new(addr, 3)),
+                Some(tls),
+            );
+            wait_for_listener(addr).await;
+
+            assert_rejected::<ChordCapnpClient>(addr, fixture.client_config()).await;
+        }
+    }
+
+    mod overload {
+        use std::time::Duration;
+
+        use chord_rs_core::client::ClientError;
+        use chord_rs_core::testing::{free_addr, wait_for_listener};
+
+        use super::*;
+
+        async fn serve(max_connections: usize, queue: usize, timeout: Duration) -> SocketAddr {
+            let addr = free_addr();
+            let node = Arc::new(NodeService::<ChordCapnpClient>::new(addr, 3));
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                let server = Server::with_service(addr, node, None).accept_queue(queue, timeout);
+                rt.block_on(server.run(max_connections))
+                    .expect("Failed to run the server");
+            });
+            wait_for_listener(addr).await;
+            // Let the server release the slot of the probe connection
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            addr
+        }
+
+        async fn client(addr: SocketAddr) -> ChordCapnpClient {
+            ChordCapnpClient::init(addr.into(), ClientConfig::default())
+                .await
+                .unwrap()
+        }
+
+        fn assert_overloaded(result: error_stack::Result<(), ClientError>) {
+            let report = result.expect_err("the server should be overloaded");
+            assert!(
+                matches!(report.current_context(), ClientError::Overloaded(_)),
+                "failed with {:?}",
+                report
+            );
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn connections_over_the_limit_are_rejected_as_overloaded() {
+            let addr = serve(1, 0, Duration::from_secs(5)).await;
+            let first = client(addr).await;
+            first.ping().await.unwrap();
+
+            assert_overloaded(client(addr).await.ping().await);
+            first.ping().await.unwrap();
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn queued_connections_are_served_once_a_slot_is_freed() {
+            let addr = serve(1, 1, Duration::from_secs(5)).await;
+            let first = client(addr).await;
+            first.ping().await.unwrap();
+
+            let queued = tokio::spawn(async move { client(addr).await.ping().await });
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            drop(first);
+
+            queued.await.unwrap().unwrap();
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn queued_connections_are_rejected_after_the_timeout() {
+            let addr = serve(1, 1, Duration::from_millis(200)).await;
+            let first = client(addr).await;
+            first.ping().await.unwrap();
+
+            assert_overloaded(client(addr).await.ping().await);
+        }
+    }
+
+    mod discovery {
+        use std::collections::HashMap;
+        use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+        use std::time::Duration;
+
+        use chord_rs_core::discovery::{self, DiscoveryConfig};
+        use chord_rs_core::testing::{free_addr, wait_for_listener};
+        use chord_rs_core::NodeId;
+
+        use super::*;
+
+        /// Follow the successors from the first node, return the ids of the nodes of its ring
+        async fn ring(
+            nodes: &HashMap<SocketAddr, Arc<NodeService<ChordCapnpClient>>>,
+        ) -> Vec<NodeId> {
+            let mut node = nodes.values().next().unwrap();
+            let mut ids = Vec::new();
+            for _ in 0..=nodes.len() {
+                if ids.contains(&node.id()) {
+                    break;
+                }
+                ids.push(node.id());
+                let successor = node.get_successor().await.unwrap();
+                node = &nodes[&successor.addr()];
+            }
+            ids
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn nodes_started_at_once_form_a_single_ring() {
+            const NODES: usize = 4;
+            let port = UdpSocket::bind("0.0.0.0:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let config = DiscoveryConfig {
+                group: SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 99), port),
+                interval: Duration::from_millis(200),
+                ..DiscoveryConfig::default()
+            };
+
+            let mut nodes = HashMap::new();
+            let mut previous: Option<Arc<NodeService<ChordCapnpClient>>> = None;
+            for _ in 0..NODES {
+                let addr = free_addr();
+                let node = Arc::new(NodeService::<ChordCapnpClient>::new(addr, 3));
+                serve(addr, node.clone());
+                wait_for_listener(addr).await;
+                // Nodes which start at once join whichever node they hear first,
+                // which splits them into rings of two nodes
+                match previous.take() {
+                    Some(other) => node.join(other.node()).await.unwrap(),
+                    None => previous = Some(node.clone()),
+                }
+                chord_rs_core::server::background_tasks(node.clone());
+                nodes.insert(addr, node);
+            }
+            // Let the rings stabilize, so none of the nodes is alone
+            tokio::time::sleep(Duration::from_millis(2500)).await;
+            assert_eq!(ring(&nodes).await.len(), 2);
+
+            for node in nodes.values() {
+                discovery::spawn(node.clone(), config.clone());
+            }
+
+            for _ in 0..60 {
+                if ring(&nodes).await.len() == NODES {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            panic!(
+                "The nodes didn't form a single ring: {:?}",
+                ring(&nodes).await
+            );
+        }
     }
 }
@@ -1,9 +1,43 @@
-use chord_rs_core::client::ClientError;
+use chord_rs_core::{client::ClientError, error::ServiceError};
 
 use crate::client::CapnpClientError;
 
 use super::ParserError;
 
+/// Prefix capnp-rpc adds to the description of the errors returned by the other side
+const REMOTE_EXCEPTION: &str = "remote exception: ";
+
+/// Create the error returned for a failed call
+///
+/// # Wire contract
+///
+/// Cap'n'proto exceptions only carry a type and a description, so the kind of the error is
+/// carried in the description. This is version 1 of the contract, since chord-capnp 0.1.0:
+///
+/// * [`ServiceError::Overloaded`] is an `overloaded` exception described by the message
+/// * Other kinds are `failed` exceptions described as `<code>: <message>`, where `<code>` is
+///   the [`ServiceError::code`] of the kind, e.g. `not_owner: No closer node`
+///
+/// Codes are only ever added, never renamed. An unknown code, or a `failed` exception of any
+/// other shape, is decoded as an unexpected error. A change to the contract bumps its version,
+/// here and in `capnp/chord.capnp`.
+pub(crate) fn encode_error(kind: ServiceError, message: impl std::fmt::Display) -> capnp::Error {
+    match kind {
+        ServiceError::Overloaded => capnp::Error::overloaded(message.to_string()),
+        kind => capnp::Error::failed(format!("{}: {}", kind.code(), message)),
+    }
+}
+
+/// Get the kind and the message of an error created by [`encode_error`]
+fn decode_error(description: &str) -> Option<(ServiceError, String)> {
+    let description = description
+        .strip_prefix(REMOTE_EXCEPTION)
+        .unwrap_or(description);
+    let (code, message) = description.split_once(": ")?;
+
+    Some((ServiceError::from_code(code)?, message.to_string()))
+}
+
 impl From<ParserError> for CapnpClientError {
     fn from(value: ParserError) -> Self {
         CapnpClientError::InvalidRequest(value.to_string())
@@ -15,6 +49,8 @@ impl Into<ClientError> for CapnpClientError {
         match self {
             CapnpClientError::InvalidRequest(m) => ClientError::InvalidRequest(m),
             CapnpClientError::ConnectionFailed(m) => ClientError::ConnectionFailed(m),
+            CapnpClientError::Overloaded(m) => ClientError::Overloaded(m),
+            CapnpClientError::Service(kind, m) => ClientError::from_service(kind, m),
             CapnpClientError::Unexpected(_) => ClientError::Unexpected,
         }
     }
@@ -24,8 +60,11 @@ impl From<capnp::Error> for CapnpClientError {
     fn from(value: capnp::Error) -> Self {
         log::error!("capnp error: {:?}", value);
         match value.kind {
-            capnp::ErrorKind::Failed => CapnpClientError::Unexpected(value.to_string()),
-            capnp::ErrorKind::Overloaded => CapnpClientError::Unexpected(value.to_string()),
+            capnp::ErrorKind::Failed => match decode_error(&value.description) {
+                Some((kind, message)) => CapnpClientError::Service(kind, message),
+                None => CapnpClientError::Unexpected(value.to_string()),
+            },
+            capnp::ErrorKind::Overloaded => CapnpClientError::Overloaded(value.description),
             capnp::ErrorKind::Disconnected => CapnpClientError::ConnectionFailed(value.to_string()),
             capnp::ErrorKind::Unimplemented => CapnpClientError::Unexpected(value.to_string()),
         }
@@ -38,3 +77,29 @@ impl From<capnp::NotInSchema> for CapnpClientError {
         CapnpClientError::Unexpected(value.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_errors_are_decoded_by_their_code() {
+        let error = encode_error(ServiceError::NotOwner, "no closer node");
+        let error = capnp::Error {
+            description: format!("{}{}", REMOTE_EXCEPTION, error.description),
+            ..error
+        };
+        let error: ClientError = CapnpClientError::from(error).into();
+        assert!(matches!(error, ClientError::NotOwner(msg) if msg == "no closer node"));
+
+        let error: ClientError =
+            CapnpClientError::from(encode_error(ServiceError::Overloaded, "busy")).into();
+        assert!(matches!(error, ClientError::Overloaded(msg) if msg == "busy"));
+
+        let error: ClientError = CapnpClientError::from(capnp::Error::failed(
+            "Unauthenticated: not a code".to_string(),
+        ))
+        .into();
+        assert!(matches!(error, ClientError::Unexpected));
+    }
+}
@@ -0,0 +1,56 @@
+use chord_rs_core::lookup::{NextHop, PrecedingFinger};
+
+use crate::chord_capnp;
+use crate::chord_capnp::chord_node::next_hop_results;
+use crate::client::CapnpClientError;
+
+use super::ResultBuilder;
+
+/// Map the capnp results of a next hop call to a chord_rs_core next hop
+pub(crate) fn read_next_hop(
+    value: next_hop_results::Reader<'_>,
+) -> Result<NextHop, CapnpClientError> {
+    let node = value.get_node()?.try_into()?;
+    let successor = value.get_successor()?.try_into()?;
+    let finger = match value.get_finger()?.which()? {
+        chord_capnp::option::None(()) => None,
+        chord_capnp::option::Some(finger) => {
+            let finger = finger?;
+            Some(PrecedingFinger {
+                index: finger.get_index(),
+                node: finger.get_node()?.try_into()?,
+            })
+        }
+    };
+
+    Ok(NextHop {
+        node,
+        successor,
+        finger,
+    })
+}
+
+/// Insert a `NextHop` into a `NextHopResults` struct.
+impl ResultBuilder<NextHop> for chord_capnp::chord_node::NextHopResults {
+    type Output = ();
+    #[inline]
+    fn insert(mut self, value: NextHop) -> Result<Self::Output, capnp::Error> {
+        let mut results = self.get();
+        results.reborrow().init_node().insert(value.node)?;
+        results
+            .reborrow()
+            .init_successor()
+            .insert(value.successor)?;
+
+        let mut finger = results.init_finger();
+        if let Some(value) = value.finger {
+            let mut some = finger.init_some();
+            some.set_index(value.index);
+            some.init_node().insert(value.node)?;
+        } else {
+            finger.set_none(());
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,10 @@
 use std::fmt::Display;
 
 mod errors;
+mod lookup;
 mod node;
+pub(crate) use errors::encode_error;
+pub(crate) use lookup::read_next_hop;
 pub use node::*;
 
 /// Trait for inserting a value into a Cap'n'proto result builder.
@@ -20,14 +23,14 @@ pub trait ResultBuilder<T>: Sized {
 
 #[derive(Debug)]
 pub enum ParserError {
-    InvalidNode,
+    InvalidNode(String),
     InvalidIp(String),
 }
 
 impl Display for ParserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::InvalidNode => write!(f, "Invalid node"),
+            Self::InvalidNode(msg) => write!(f, "Invalid node: {}", msg),
             Self::InvalidIp(msg) => write!(f, "{}", msg),
         }
     }
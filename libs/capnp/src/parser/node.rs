@@ -15,9 +15,20 @@ impl TryFrom<node::Reader<'_>> for Node {
 
     fn try_from(value: node::Reader<'_>) -> Result<Self, Self::Error> {
         let id = value.get_id();
-        let addr: SocketAddr = value.get_address().unwrap().try_into()?;
-
-        Ok(Node::with_id(id, addr))
+        let addr: SocketAddr = value
+            .get_address()
+            .map_err(|err| super::ParserError::InvalidIp(format!("Missing address: {}", err)))?
+            .try_into()?;
+        let host = value
+            .get_host()
+            .map_err(|err| super::ParserError::InvalidNode(format!("Invalid host: {}", err)))?;
+        let host = (!host.is_empty()).then(|| host.to_string());
+
+        let node = Node::with_id(id, addr).with_host(host);
+        node.validate()
+            .map_err(|err| super::ParserError::InvalidNode(err.to_string()))?;
+
+        Ok(node)
     }
 }
 
@@ -27,7 +38,10 @@ impl TryFrom<ip_address::Reader<'_>> for SocketAddr {
 
     fn try_from(addr: ip_address::Reader<'_>) -> Result<Self, Self::Error> {
         let port = addr.get_port();
-        let address = match addr.which().unwrap() {
+        let version = addr
+            .which()
+            .map_err(|err| super::ParserError::InvalidIp(format!("Unknown IP version: {}", err)))?;
+        let address = match version {
             ip_address::Which::Ipv4(Ok(ipv4)) => {
                 let mut array = [0; 4];
                 if let Some(ip) = ipv4.as_slice() {
@@ -87,6 +101,18 @@ impl ResultBuilder<Node> for chord_capnp::chord_node::FindSuccessorResults {
     }
 }
 
+/// Insert a `Node` into a `GetSuccessorResults` struct.
+impl ResultBuilder<Node> for chord_capnp::chord_node::GetSuccessorResults {
+    type Output = ();
+    #[inline]
+    fn insert(mut self, value: Node) -> Result<Self::Output, capnp::Error> {
+        let node = self.get().init_node();
+        node.insert(value)?;
+
+        Ok(())
+    }
+}
+
 /// Insert a `Vec<Node>` into a `GetSuccessorListResults` struct.
 impl ResultBuilder<Vec<Node>> for chord_capnp::chord_node::GetSuccessorListResults {
     type Output = ();
@@ -122,7 +148,10 @@ impl ResultBuilder<Node> for chord_capnp::chord_node::node::Builder<'_> {
     #[inline]
     fn insert(mut self, value: Node) -> Result<Self::Output, capnp::Error> {
         self.set_id(value.id().into());
-        self.init_address().insert(value.addr())?;
+        self.reborrow().init_address().insert(value.addr())?;
+        if let Some(host) = value.host() {
+            self.set_host(host);
+        }
 
         Ok(())
     }
@@ -283,4 +312,27 @@ mod tests {
             "IPv4 should contain 4 chunks".to_string()
         );
     }
+
+    #[test]
+    fn test_invalid_node_to_deserialization() {
+        use chord_rs_core::Node;
+
+        let message = message::Builder::new_default();
+        let reader: chord_capnp::chord_node::node::Reader = message.get_root_as_reader().unwrap();
+        assert!(Node::try_from(reader).is_err());
+
+        for node in [
+            Node::new("127.0.0.1:0".parse().unwrap()),
+            Node::new("127.0.0.1:8080".parse().unwrap()).with_host(Some("not a host".into())),
+        ] {
+            let mut message = message::Builder::new_default();
+            let builder = message.init_root::<chord_capnp::chord_node::node::Builder<'_>>();
+            builder.insert(node).unwrap();
+
+            let reader: chord_capnp::chord_node::node::Reader =
+                message.get_root_as_reader().unwrap();
+            let result = Node::try_from(reader);
+            assert!(result.unwrap_err().to_string().starts_with("Invalid node"));
+        }
+    }
 }
@@ -1,28 +1,33 @@
-use std::{fmt::Display, sync::Arc};
+use std::{fmt::Display, sync::Arc, time::Duration};
 
-use chord_rs_core::{Node, NodeService};
+use chord_rs_core::{deadline, error::ServiceError, Client, Node, NodeService};
+use error_stack::Report;
+use tokio::time::Instant;
 
-use crate::{chord_capnp, parser::ResultBuilder};
-
-use super::client::ChordCapnpClient;
+use crate::{
+    chord_capnp,
+    parser::{encode_error, ResultBuilder},
+};
 
 /// Implementation of the chord_node interface
-pub(crate) struct NodeServerImpl {
-    node: Arc<NodeService<ChordCapnpClient>>,
+pub(crate) struct NodeServerImpl<C: Client> {
+    node: Arc<NodeService<C>>,
 }
 
-impl NodeServerImpl {
+impl<C: Client + Clone + Sync + Send + 'static> NodeServerImpl<C> {
     /// Create a new instance of the Cap'n'proto server
     ///
     /// # Arguments
     ///
     /// * `node` - The Chord node service.
-    pub fn new(node: Arc<NodeService<ChordCapnpClient>>) -> Self {
+    pub fn new(node: Arc<NodeService<C>>) -> Self {
         Self { node }
     }
 }
 
-impl chord_capnp::chord_node::Server for NodeServerImpl {
+impl<C: Client + Clone + Sync + Send + 'static> chord_capnp::chord_node::Server
+    for NodeServerImpl<C>
+{
     /// Ping the node
     ///
     /// Just responds with an empty message.
@@ -39,7 +44,8 @@ impl chord_capnp::chord_node::Server for NodeServerImpl {
     ///
     /// # Arguments
     ///
-    /// * `params` - Cap'n'proto message containing the id to find the successor of.
+    /// * `params` - Cap'n'proto message containing the id to find the successor of
+    ///   and the time left to the deadline of the caller.
     /// * `results` - Cap'n'proto message to write the successor to.
     fn find_successor(
         &mut self,
@@ -51,11 +57,68 @@ impl chord_capnp::chord_node::Server for NodeServerImpl {
         let service = self.node.clone();
 
         ::capnp::capability::Promise::from_future(async move {
-            let id = params.get()?.get_id();
-            let node = service
-                .find_successor(id.into())
+            let params = params.get().map_err(invalid_argument)?;
+            let id = params.get_id();
+            let timeout_ms = params.get_timeout_ms();
+            let deadline = match timeout_ms {
+                0 => None,
+                timeout_ms => Some(
+                    Instant::now()
+                        .checked_add(Duration::from_millis(timeout_ms))
+                        .ok_or_else(|| {
+                            invalid_argument(format!("Invalid timeout {}ms", timeout_ms))
+                        })?,
+                ),
+            };
+            let node = deadline::scope(deadline, service.find_successor_uncached(id.into()))
                 .await
-                .map_err(error_parser)?;
+                .map_err(service_error)?;
+
+            results.insert(node)?;
+
+            Ok(())
+        })
+    }
+
+    /// Get the routing information of the node for a step of an iterative lookup
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Cap'n'proto message containing the id being looked up.
+    /// * `results` - Cap'n'proto message to write the successor and the closest preceding finger to.
+    fn next_hop(
+        &mut self,
+        params: chord_capnp::chord_node::NextHopParams,
+        results: chord_capnp::chord_node::NextHopResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        log::trace!("NextHop received");
+
+        let service = self.node.clone();
+
+        ::capnp::capability::Promise::from_future(async move {
+            let id = params.get().map_err(invalid_argument)?.get_id();
+            results.insert(service.next_hop(id.into()))?;
+
+            Ok(())
+        })
+    }
+
+    /// Get the successor of the node
+    ///
+    /// # Arguments
+    ///
+    /// * `_params` - Cap'n'proto message, not used.
+    /// * `results` - Cap'n'proto message to write the successor to.
+    fn get_successor(
+        &mut self,
+        _params: chord_capnp::chord_node::GetSuccessorParams,
+        results: chord_capnp::chord_node::GetSuccessorResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        log::trace!("GetSuccessor received");
+
+        let service = self.node.clone();
+        ::capnp::capability::Promise::from_future(async move {
+            let node = service.get_successor().await.map_err(service_error)?;
 
             results.insert(node)?;
 
@@ -72,7 +135,7 @@ impl chord_capnp::chord_node::Server for NodeServerImpl {
 
         let service = self.node.clone();
         ::capnp::capability::Promise::from_future(async move {
-            let node = service.get_successor_list().await.map_err(error_parser)?;
+            let node = service.get_successor_list().await.map_err(service_error)?;
 
             results.insert(node)?;
 
@@ -96,7 +159,7 @@ impl chord_capnp::chord_node::Server for NodeServerImpl {
         let service = self.node.clone();
 
         ::capnp::capability::Promise::from_future(async move {
-            let maybe_node = service.get_predecessor().await.map_err(error_parser)?;
+            let maybe_node = service.get_predecessor().await.map_err(service_error)?;
             results.insert(maybe_node)?;
 
             Ok(())
@@ -107,7 +170,7 @@ impl chord_capnp::chord_node::Server for NodeServerImpl {
     ///
     /// # Arguments
     ///
-    /// * `params` - Cap'n'proto message containing the potential new predecessor.
+    /// * `params` - Cap'n'proto message containing the potential new predecessor and its token.
     /// * `_results` - Cap'n'proto message, not used.
     fn notify(
         &mut self,
@@ -119,18 +182,100 @@ impl chord_capnp::chord_node::Server for NodeServerImpl {
         let service = self.node.clone();
 
         ::capnp::capability::Promise::from_future(async move {
-            let node = params.get()?.get_node()?;
-            let node: Node = node.try_into().unwrap(); // TODO: error handling
-            service.notify(node);
+            let params = params.get().map_err(invalid_argument)?;
+            let node: Node = params
+                .get_node()
+                .map_err(invalid_argument)?
+                .try_into()
+                .map_err(invalid_argument)?;
+            let token = params.get_token().map_err(invalid_argument)?;
+            let token = (!token.is_empty()).then_some(token);
+            service.notify(node, token).map_err(service_error)?;
 
             Ok(())
         })
     }
 }
 
-fn error_parser<T>(err: T) -> capnp::Error
-where
-    T: Display,
-{
-    capnp::Error::failed(format!("{}", err))
+/// Implementation of the chord_node interface for connections over the limit
+///
+/// Every call fails with an overload error, so the peer can back off and retry
+/// instead of seeing the connection drop.
+pub(crate) struct OverloadedServer;
+
+impl OverloadedServer {
+    fn overloaded() -> capnp::capability::Promise<(), capnp::Error> {
+        capnp::capability::Promise::err(encode_error(
+            ServiceError::Overloaded,
+            "Server is overloaded",
+        ))
+    }
+}
+
+impl chord_capnp::chord_node::Server for OverloadedServer {
+    fn ping(
+        &mut self,
+        _params: chord_capnp::chord_node::PingParams,
+        _results: chord_capnp::chord_node::PingResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        Self::overloaded()
+    }
+
+    fn find_successor(
+        &mut self,
+        _params: chord_capnp::chord_node::FindSuccessorParams,
+        _results: chord_capnp::chord_node::FindSuccessorResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        Self::overloaded()
+    }
+
+    fn next_hop(
+        &mut self,
+        _params: chord_capnp::chord_node::NextHopParams,
+        _results: chord_capnp::chord_node::NextHopResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        Self::overloaded()
+    }
+
+    fn get_successor(
+        &mut self,
+        _params: chord_capnp::chord_node::GetSuccessorParams,
+        _results: chord_capnp::chord_node::GetSuccessorResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        Self::overloaded()
+    }
+
+    fn get_successor_list(
+        &mut self,
+        _params: chord_capnp::chord_node::GetSuccessorListParams,
+        _results: chord_capnp::chord_node::GetSuccessorListResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        Self::overloaded()
+    }
+
+    fn get_predecessor(
+        &mut self,
+        _params: chord_capnp::chord_node::GetPredecessorParams,
+        _results: chord_capnp::chord_node::GetPredecessorResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        Self::overloaded()
+    }
+
+    fn notify(
+        &mut self,
+        _params: chord_capnp::chord_node::NotifyParams,
+        _results: chord_capnp::chord_node::NotifyResults,
+    ) -> capnp::capability::Promise<(), capnp::Error> {
+        Self::overloaded()
+    }
+}
+
+/// Map a service error to an error carrying its kind, which the client maps back
+fn service_error(err: Report<ServiceError>) -> capnp::Error {
+    encode_error(*err.current_context(), &err)
+}
+
+/// Reject a malformed request
+fn invalid_argument(err: impl Display) -> capnp::Error {
+    encode_error(ServiceError::InvalidArgument, err)
 }
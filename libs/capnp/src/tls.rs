@@ -0,0 +1,157 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use chord_rs_core::client::TlsConfig;
+use error_stack::{IntoReport, Report, Result, ResultExt};
+use thiserror::Error;
+use tokio_rustls::{
+    rustls::{
+        self, server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore,
+        ServerName,
+    },
+    TlsAcceptor, TlsConnector,
+};
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to load TLS files")]
+    Load,
+    #[error("Invalid TLS configuration")]
+    InvalidConfig,
+}
+
+/// Client side of the TLS connections to the nodes
+#[derive(Clone)]
+pub(crate) struct ClientTls {
+    pub(crate) connector: TlsConnector,
+    /// The name the certificate of the node is verified against
+    pub(crate) server_name: ServerName,
+}
+
+impl ClientTls {
+    /// Create the client side of the TLS connections
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The TLS configuration
+    pub(crate) fn new(config: &TlsConfig) -> Result<Self, TlsError> {
+        Ok(Self {
+            connector: connector(config)?,
+            server_name: server_name(config)?,
+        })
+    }
+}
+
+/// Create a connector for the client side of the connections
+///
+/// # Arguments
+///
+/// * `config` - The TLS configuration, the identity is presented to servers requiring client certificates
+fn connector(config: &TlsConfig) -> Result<TlsConnector, TlsError> {
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store(&config.ca_cert)?);
+
+    let client_config = match &config.identity {
+        Some(identity) => builder
+            .with_single_cert(certificates(&identity.cert)?, private_key(&identity.key)?)
+            .into_report()
+            .change_context(TlsError::InvalidConfig)?,
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(TlsConnector::from(Arc::new(client_config)))
+}
+
+/// Create an acceptor for the server side of the connections
+///
+/// # Arguments
+///
+/// * `config` - The TLS configuration, it has to contain the server identity
+pub(crate) fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor, TlsError> {
+    let identity = config
+        .identity
+        .as_ref()
+        .ok_or_else(|| Report::new(TlsError::InvalidConfig))
+        .attach_printable("The server requires a certificate and a private key")?;
+
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let builder = if config.client_auth {
+        builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(root_store(
+            &config.ca_cert,
+        )?))
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    let server_config = builder
+        .with_single_cert(certificates(&identity.cert)?, private_key(&identity.key)?)
+        .into_report()
+        .change_context(TlsError::InvalidConfig)?;
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Get the name the certificates of the servers are verified against
+fn server_name(config: &TlsConfig) -> Result<ServerName, TlsError> {
+    ServerName::try_from(config.server_name.as_str())
+        .into_report()
+        .change_context(TlsError::InvalidConfig)
+        .attach_printable_lazy(|| format!("Invalid server name: {}", config.server_name))
+}
+
+fn root_store(path: &Path) -> Result<RootCertStore, TlsError> {
+    let mut store = RootCertStore::empty();
+    for cert in certificates(path)? {
+        store
+            .add(&cert)
+            .into_report()
+            .change_context(TlsError::InvalidConfig)
+            .attach_printable_lazy(|| format!("Invalid CA certificate in {:?}", path))?;
+    }
+
+    Ok(store)
+}
+
+fn certificates(path: &Path) -> Result<Vec<Certificate>, TlsError> {
+    let certs = rustls_pemfile::certs(&mut reader(path)?)
+        .into_report()
+        .change_context(TlsError::Load)
+        .attach_printable_lazy(|| format!("Invalid certificate file {:?}", path))?;
+
+    if certs.is_empty() {
+        return Err(Report::new(TlsError::Load))
+            .attach_printable(format!("No certificate found in {:?}", path));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn private_key(path: &Path) -> Result<PrivateKey, TlsError> {
+    let mut reader = reader(path)?;
+    loop {
+        let item = rustls_pemfile::read_one(&mut reader)
+            .into_report()
+            .change_context(TlsError::Load)
+            .attach_printable_lazy(|| format!("Invalid private key file {:?}", path))?;
+
+        match item {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => {
+                return Err(Report::new(TlsError::Load))
+                    .attach_printable(format!("No private key found in {:?}", path))
+            }
+        }
+    }
+}
+
+fn reader(path: &Path) -> Result<BufReader<File>, TlsError> {
+    let file = File::open(path)
+        .into_report()
+        .change_context(TlsError::Load)
+        .attach_printable_lazy(|| format!("Failed to open {:?}", path))?;
+
+    Ok(BufReader::new(file))
+}
@@ -0,0 +1,161 @@
+use std::{
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chord_rs_core::{Client, NodeService};
+use tokio::{
+    runtime::Builder,
+    sync::{mpsc, OwnedSemaphorePermit},
+    task::LocalSet,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    chord_capnp, rpc_system,
+    server::{NodeServerImpl, OverloadedServer},
+};
+
+/// How long the peer of a rejected connection has to read the overload error
+const REJECTED_LINGER: Duration = Duration::from_secs(1);
+
+/// A connection accepted by the server
+pub(crate) enum Connection {
+    /// The connection is served, it holds its slot until it's closed
+    Accepted {
+        stream: TcpStream,
+        permit: OwnedSemaphorePermit,
+    },
+    /// The server is overloaded, every call on the connection fails with an overload error
+    Rejected(TcpStream),
+}
+
+/// A thread serving the connections it receives from the server
+///
+/// Cap'n'proto RPC isn't `Send`, so each worker runs its own runtime with a `LocalSet`
+/// and its own instance of the chord node interface. The node service is shared.
+pub(crate) struct Worker {
+    sender: mpsc::UnboundedSender<Connection>,
+}
+
+impl Worker {
+    /// Start the worker thread
+    ///
+    /// The thread stops once the worker is dropped and all its connections are closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the worker, used to name the thread
+    /// * `node` - The node service to expose
+    /// * `acceptor` - Accepts TLS connections, plaintext TCP is used when it's not set
+    pub(crate) fn spawn<C: Client + Clone + Sync + Send + 'static>(
+        id: usize,
+        node: Arc<NodeService<C>>,
+        acceptor: Option<TlsAcceptor>,
+    ) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Connection>();
+
+        std::thread::Builder::new()
+            .name(format!("capnp-worker-{}", id))
+            .spawn(move || {
+                let rt = Builder::new_current_thread().enable_all().build().unwrap();
+                let local = LocalSet::new();
+                local.block_on(&rt, async move {
+                    let chord_node_client: chord_capnp::chord_node::Client =
+                        capnp_rpc::new_client(NodeServerImpl::new(node));
+                    let overloaded_client: chord_capnp::chord_node::Client =
+                        capnp_rpc::new_client(OverloadedServer);
+
+                    while let Some(connection) = receiver.recv().await {
+                        let acceptor = acceptor.clone();
+                        match connection {
+                            Connection::Accepted { stream, permit } => {
+                                let client = chord_node_client.clone().client;
+                                tokio::task::spawn_local(async move {
+                                    Self::serve(stream, client, acceptor).await;
+                                    log::trace!("Semaphore released");
+                                    drop(permit);
+                                });
+                            }
+                            Connection::Rejected(stream) => {
+                                let client = overloaded_client.clone().client;
+                                tokio::task::spawn_local(tokio::time::timeout(
+                                    REJECTED_LINGER,
+                                    Self::serve(stream, client, acceptor),
+                                ));
+                            }
+                        }
+                    }
+                });
+                // Let the open connections finish
+                rt.block_on(local);
+            })
+            .expect("Failed to spawn a capnp worker thread");
+
+        Self { sender }
+    }
+
+    /// Hand a connection over to the worker
+    pub(crate) fn send(&self, connection: Connection) {
+        if self.sender.send(connection).is_err() {
+            log::error!("capnp worker has stopped, dropping the connection");
+        }
+    }
+
+    async fn serve(
+        stream: TcpStream,
+        client: capnp::capability::Client,
+        acceptor: Option<TlsAcceptor>,
+    ) {
+        let stream = match tokio::net::TcpStream::from_std(stream) {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::error!("Failed to register the connection: {}", err);
+                return;
+            }
+        };
+
+        let rpc_system = match acceptor {
+            Some(acceptor) => match acceptor.accept(stream).await {
+                Ok(stream) => rpc_system(stream, client),
+                Err(err) => {
+                    log::debug!("TLS handshake failed: {}", err);
+                    return;
+                }
+            },
+            None => rpc_system(stream, client),
+        };
+        if let Err(err) = rpc_system.await {
+            log::error!("rpc system error: {}", err);
+        }
+    }
+}
+
+/// The workers of a server, connections are handed over to them in turns
+pub(crate) struct Workers {
+    workers: Vec<Worker>,
+    next: AtomicUsize,
+}
+
+impl Workers {
+    pub(crate) fn new(workers: Vec<Worker>) -> Self {
+        Self {
+            workers,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Hand a connection over to the next worker
+    pub(crate) fn send(&self, connection: Connection) {
+        let next = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        self.workers[next].send(connection);
+    }
+}
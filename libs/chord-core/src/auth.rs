@@ -0,0 +1,113 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::Node;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Secret shared by the nodes of a ring
+///
+/// Calls changing the membership of the ring carry a token, which is an HMAC of the announced
+/// node. Nodes configured with a secret reject such calls unless the token is signed with the
+/// same secret, so a process which doesn't know it can't take a position in the ring.
+#[derive(Clone)]
+pub struct ClusterSecret {
+    key: Arc<[u8]>,
+}
+
+impl ClusterSecret {
+    /// Create a secret from the given bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The secret, it should be long and random
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            key: key.into().into(),
+        }
+    }
+
+    /// Sign the node announced by a membership changing call
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The announced node
+    pub fn sign(&self, node: &Node) -> Vec<u8> {
+        self.mac(node).finalize().into_bytes().to_vec()
+    }
+
+    /// Check that the token was signed for the node using this secret
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The announced node
+    /// * `token` - The token sent with the call
+    pub fn verify(&self, node: &Node, token: &[u8]) -> bool {
+        self.mac(node).verify_slice(token).is_ok()
+    }
+
+    /// The fields of variable length are prefixed with their length, and the host with whether
+    /// it's set, so that different nodes can't produce the same input
+    fn mac(&self, node: &Node) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any size");
+        let update_field = |mac: &mut HmacSha256, field: &[u8]| {
+            mac.update(&(field.len() as u64).to_be_bytes());
+            mac.update(field);
+        };
+
+        mac.update(&node.id.0.to_be_bytes());
+        update_field(&mut mac, node.addr.to_string().as_bytes());
+        match &node.host {
+            Some(host) => {
+                mac.update(&[1]);
+                update_field(&mut mac, host.as_bytes());
+            }
+            None => mac.update(&[0]),
+        }
+        mac
+    }
+}
+
+impl Debug for ClusterSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ClusterSecret(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn node(id: u64) -> Node {
+        Node::with_id(id, SocketAddr::from(([127, 0, 0, 1], 42000 + id as u16)))
+    }
+
+    #[test]
+    fn token_is_valid_only_for_the_signed_node_and_secret() {
+        let secret = ClusterSecret::new("secret");
+        let token = secret.sign(&node(1));
+
+        assert!(secret.verify(&node(1), &token));
+        assert!(!secret.verify(&node(2), &token));
+        assert!(!secret.verify(&Node::with_id(1, node(2).addr), &token));
+        assert!(!secret.verify(&node(1).with_host(Some("other".to_string())), &token));
+        assert!(!ClusterSecret::new("other").verify(&node(1), &token));
+        assert!(!secret.verify(&node(1), &[]));
+    }
+
+    #[test]
+    fn token_is_not_valid_for_a_node_with_the_same_concatenated_fields() {
+        let secret = ClusterSecret::new("secret");
+        let signed = Node::with_id(1, SocketAddr::from(([127, 0, 0, 1], 4200)))
+            .with_host(Some("1.example".to_string()));
+        let token = secret.sign(&signed);
+
+        let forged = Node::with_id(1, SocketAddr::from(([127, 0, 0, 1], 42001)))
+            .with_host(Some(".example".to_string()));
+        assert!(!secret.verify(&forged, &token));
+    }
+}
@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::auth::ClusterSecret;
+
+use super::{Connector, PoolConfig};
+
+/// Configuration of the clients a node uses to talk to other nodes
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfig {
+    /// TLS configuration, plaintext TCP is used when it's not set
+    pub tls: Option<TlsConfig>,
+    /// Secret of the ring, used to sign the notify calls
+    pub secret: Option<ClusterSecret>,
+    /// Limits of the pool the clients are kept in
+    pub pool: PoolConfig,
+    /// Timeouts of the calls
+    pub timeouts: Timeouts,
+    /// Creates the clients when they are chosen at runtime, see [`super::DynClient`]
+    pub connector: Option<Arc<dyn Connector>>,
+}
+
+impl ClientConfig {
+    /// Create a config using the given TLS configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `tls` - The TLS configuration, `None` for plaintext TCP
+    pub fn with_tls(tls: Option<TlsConfig>) -> Self {
+        Self {
+            tls,
+            ..Self::default()
+        }
+    }
+
+    /// Sign the notify calls using the given secret
+    ///
+    /// # Arguments
+    ///
+    /// * `secret` - The secret of the ring, `None` to send unsigned calls
+    pub fn secret(mut self, secret: Option<ClusterSecret>) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    /// Create the clients with the given connector
+    ///
+    /// # Arguments
+    ///
+    /// * `connector` - The connector used by [`super::DynClient`]
+    pub fn connector(mut self, connector: Option<Arc<dyn Connector>>) -> Self {
+        self.connector = connector;
+        self
+    }
+}
+
+/// Timeouts of the calls made by a client
+///
+/// A call made while handling a call from another node is also limited by the time
+/// left to the deadline of that call, see [`crate::deadline`].
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    pub ping: Duration,
+    /// Timeout of a whole lookup, including the hops made by the other nodes
+    pub find_successor: Duration,
+    pub next_hop: Duration,
+    pub successor: Duration,
+    pub successor_list: Duration,
+    pub predecessor: Duration,
+    pub notify: Duration,
+}
+
+impl Timeouts {
+    /// Use the same timeout for all the calls
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The timeout of each call
+    pub fn all(timeout: Duration) -> Self {
+        Self {
+            ping: timeout,
+            find_successor: timeout,
+            next_hop: timeout,
+            successor: timeout,
+            successor_list: timeout,
+            predecessor: timeout,
+            notify: timeout,
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            find_successor: Duration::from_secs(5),
+            ..Self::all(Duration::from_secs(2))
+        }
+    }
+}
+
+/// Paths to the PEM files used to secure the traffic between nodes
+///
+/// The same configuration is used by a node when it acts as a server and as a client.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// CA certificate used to verify the certificates of the other side
+    pub ca_cert: PathBuf,
+    /// Certificate and private key of this side.
+    /// Required by servers, optional for clients unless the server requires client certificates.
+    pub identity: Option<TlsIdentity>,
+    /// Require clients to present a certificate signed by `ca_cert` (mutual TLS)
+    pub client_auth: bool,
+    /// DNS name the certificates of the servers are verified against.
+    ///
+    /// Nodes are addressed by IP, but rustls can't verify certificates issued for IP addresses,
+    /// so the certificates of all the nodes in a ring share a name instead.
+    pub server_name: String,
+}
+
+/// Certificate chain and private key, both in PEM format
+#[derive(Debug, Clone)]
+pub struct TlsIdentity {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
@@ -0,0 +1,190 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use error_stack::{Report, Result};
+
+use crate::lookup::NextHop;
+use crate::{Address, Node, NodeId};
+
+use super::{Client, ClientConfig, ClientError};
+
+/// A client of any transport
+pub type BoxedClient = Box<dyn Client + Send + Sync>;
+
+/// Creates the clients a node uses to talk to its peers
+///
+/// Unlike [`Client::init`], the connector is chosen at runtime. It may pick the transport per
+/// peer, or wrap the clients of another connector, e.g. to inject faults or record the calls.
+/// The nodes use it through [`DynClient`], see [`ClientConfig::connector`].
+#[async_trait]
+pub trait Connector: Debug + Send + Sync {
+    /// Create a client for the node on the given address
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The node address to connect to
+    /// * `config` - The client configuration
+    async fn connect(
+        &self,
+        addr: Address,
+        config: ClientConfig,
+    ) -> Result<BoxedClient, ClientError>;
+}
+
+/// Connector creating the clients of a single transport with [`Client::init`]
+pub struct InitConnector<C>(PhantomData<fn() -> C>);
+
+impl<C> InitConnector<C> {
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<C> Default for InitConnector<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Debug for InitConnector<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "InitConnector<{}>", std::any::type_name::<C>())
+    }
+}
+
+#[async_trait]
+impl<C: Client + Send + Sync + 'static> Connector for InitConnector<C> {
+    async fn connect(
+        &self,
+        addr: Address,
+        config: ClientConfig,
+    ) -> Result<BoxedClient, ClientError> {
+        let client = C::init(addr, config).await?;
+        Ok(Box::new(client))
+    }
+}
+
+/// Client created by the connector of its [`ClientConfig`]
+///
+/// It lets a node use clients chosen at runtime, e.g. `NodeService<DynClient>`.
+#[derive(Clone)]
+pub struct DynClient(Arc<dyn Client + Send + Sync>);
+
+impl DynClient {
+    pub fn new(client: BoxedClient) -> Self {
+        Self(client.into())
+    }
+}
+
+#[async_trait]
+impl Client for DynClient {
+    /// Create the client with `config.connector`, fails without one
+    async fn init(addr: Address, config: ClientConfig) -> Result<Self, ClientError> {
+        let connector = config.connector.clone().ok_or_else(|| {
+            Report::new(ClientError::InvalidConfig(
+                "No connector to create the client".into(),
+            ))
+        })?;
+        let client = connector.connect(addr, config).await?;
+        Ok(Self::new(client))
+    }
+
+    async fn find_successor(&self, id: NodeId) -> Result<Node, ClientError> {
+        self.0.find_successor(id).await
+    }
+
+    async fn next_hop(&self, id: NodeId) -> Result<NextHop, ClientError> {
+        self.0.next_hop(id).await
+    }
+
+    async fn successor(&self) -> Result<Node, ClientError> {
+        self.0.successor().await
+    }
+
+    async fn successor_list(&self) -> Result<Vec<Node>, ClientError> {
+        self.0.successor_list().await
+    }
+
+    async fn predecessor(&self) -> Result<Option<Node>, ClientError> {
+        self.0.predecessor().await
+    }
+
+    async fn notify(&self, predecessor: Node) -> Result<(), ClientError> {
+        self.0.notify(predecessor).await
+    }
+
+    async fn ping(&self) -> Result<(), ClientError> {
+        self.0.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::client::MockClient;
+    use crate::NodeService;
+
+    fn node(id: u64) -> Node {
+        Node::with_id(id, SocketAddr::from(([127, 0, 0, 1], 42000 + id as u16)))
+    }
+
+    /// Records the addresses it connects to, the clients fail every ping
+    #[derive(Debug, Default)]
+    struct Recorder {
+        connected: Mutex<Vec<Address>>,
+    }
+
+    #[async_trait]
+    impl Connector for Recorder {
+        async fn connect(
+            &self,
+            addr: Address,
+            _: ClientConfig,
+        ) -> Result<BoxedClient, ClientError> {
+            self.connected.lock().unwrap().push(addr);
+
+            let mut client = MockClient::new();
+            client.expect_find_successor().returning(|_| Ok(node(16)));
+            client
+                .expect_ping()
+                .returning(|| Err(Report::new(ClientError::ConnectionFailed("fault".into()))));
+            Ok(Box::new(client))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_node_uses_the_clients_of_the_connector() {
+        let recorder = Arc::new(Recorder::default());
+        let config = ClientConfig::default().connector(Some(recorder.clone()));
+        let service: NodeService<DynClient> =
+            NodeService::with_config(node(8).addr(), 3, config.clone());
+
+        service.join(node(16)).await.unwrap();
+        assert_eq!(service.get_successor().await.unwrap(), node(16));
+        assert_eq!(
+            *recorder.connected.lock().unwrap(),
+            vec![node(16).address()]
+        );
+
+        let client = DynClient::init(node(32).address(), config).await.unwrap();
+        assert!(matches!(
+            client.ping().await.unwrap_err().current_context(),
+            ClientError::ConnectionFailed(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_init_fails_without_a_connector() {
+        let result = DynClient::init(node(16).address(), ClientConfig::default()).await;
+
+        assert!(matches!(
+            result.err().unwrap().current_context(),
+            ClientError::InvalidConfig(_)
+        ));
+    }
+}
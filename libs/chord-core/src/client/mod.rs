@@ -1,11 +1,16 @@
+mod config;
+mod connector;
 mod pool;
 
-use crate::{Node, NodeId};
+use crate::error::ServiceError;
+use crate::lookup::NextHop;
+use crate::{Address, Node, NodeId};
 use async_trait::async_trait;
+pub use config::{ClientConfig, Timeouts, TlsConfig, TlsIdentity};
+pub use connector::{BoxedClient, Connector, DynClient, InitConnector};
 use error_stack::Result;
 use mockall::automock;
-pub use pool::ClientsPool;
-use std::net::SocketAddr;
+pub use pool::{ClientsPool, PoolConfig};
 use thiserror::Error;
 
 #[automock]
@@ -13,10 +18,16 @@ use thiserror::Error;
 pub trait Client {
     /// Init the client
     ///
+    /// It fails when the client can't be set up, e.g. when its TLS configuration is invalid.
+    /// The node itself is contacted by the calls, they fail when it can't be reached.
+    ///
     /// # Arguments
     ///
-    /// * `addr` - The node address to connect to
-    async fn init(addr: SocketAddr) -> Self;
+    /// * `addr` - The node address to connect to, a host name is resolved on connect
+    /// * `config` - The client configuration
+    async fn init(addr: Address, config: ClientConfig) -> Result<Self, ClientError>
+    where
+        Self: Sized;
 
     /// Find a successor of a given id.
     ///
@@ -25,6 +36,13 @@ pub trait Client {
     /// * `id` - The id to find the successor for
     async fn find_successor(&self, id: NodeId) -> Result<Node, ClientError>;
 
+    /// Get the routing information of the node for a step of an iterative lookup
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id being looked up
+    async fn next_hop(&self, id: NodeId) -> Result<NextHop, ClientError>;
+
     /// Get the successor of the node
     async fn successor(&self) -> Result<Node, ClientError>;
 
@@ -36,6 +54,8 @@ pub trait Client {
 
     /// Notify the node about a new predecessor
     ///
+    /// The call is signed with the cluster secret from the client config, if there's one.
+    ///
     /// # Arguments
     ///
     /// * `predecessor` - The new predecessor
@@ -51,8 +71,23 @@ pub enum ClientError {
     ConnectionFailed(String),
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
-    #[error("Client not initialized")]
-    NotInitialized,
+    /// The client can't be set up with its configuration
+    #[error("Invalid client config: {0}")]
+    InvalidConfig(String),
+    #[error("Unauthenticated: {0}")]
+    Unauthenticated(String),
+    /// The node is at capacity, the call can be retried after a backoff
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+    /// The call didn't complete within its timeout or the deadline of the caller
+    #[error("Timeout: {0}")]
+    Timeout(String),
+    /// The node doesn't own the id and doesn't know a node closer to it
+    #[error("Not the owner: {0}")]
+    NotOwner(String),
+    /// Another node of the ring has the same id as the caller
+    #[error("Duplicate node id: {0}")]
+    DuplicateId(String),
     #[error("Unexpected error")]
     Unexpected,
 
@@ -60,6 +95,8 @@ pub enum ClientError {
     PingFailed,
     #[error("Find successor failed")]
     FindSuccessorFailed,
+    #[error("Next hop failed")]
+    NextHopFailed,
     #[error("Get successor failed")]
     GetSuccessorFailed,
     #[error("Get successor list failed")]
@@ -70,6 +107,27 @@ pub enum ClientError {
     NotifyFailed,
 }
 
+impl ClientError {
+    /// Map the error returned by the node to the error of the call
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The error code sent by the node
+    /// * `message` - The message sent along with the code
+    pub fn from_service(error: ServiceError, message: String) -> Self {
+        match error {
+            ServiceError::Unexpected => Self::Unexpected,
+            ServiceError::Unavailable => Self::ConnectionFailed(message),
+            ServiceError::Unauthenticated => Self::Unauthenticated(message),
+            ServiceError::Timeout => Self::Timeout(message),
+            ServiceError::DuplicateId => Self::DuplicateId(message),
+            ServiceError::NotOwner => Self::NotOwner(message),
+            ServiceError::Overloaded => Self::Overloaded(message),
+            ServiceError::InvalidArgument => Self::InvalidRequest(message),
+        }
+    }
+}
+
 #[cfg(test)]
 impl Clone for MockClient {
     fn clone(&self) -> Self {
@@ -1,65 +1,382 @@
 use std::{
     collections::HashMap,
+    future::Future,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
+use tokio::time::Instant;
+
+use error_stack::{Report, Result};
+
 use crate::{Client, Node, NodeId};
 
+use super::{ClientConfig, ClientError};
+
+/// Limits of the [`ClientsPool`]
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Maximum number of clients, the least recently used ones are evicted over it
+    pub max_size: usize,
+    /// Clients which aren't used for this long are evicted
+    pub idle_timeout: Duration,
+    /// Delay before a failing client is re-initialized, doubled on every failure.
+    /// The calls to an overloaded node back off the same way.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay before a failing client is re-initialized
+    pub max_backoff: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 256,
+            idle_timeout: Duration::from_secs(300),
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Delay after consecutive failures, doubled on every failure
+#[derive(Debug, Default, Clone, Copy)]
+struct Backoff {
+    /// Consecutive failures
+    failures: u32,
+    last_failure: Option<Instant>,
+    /// The end of the delay after the last failure
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    /// Record a failure and return the delay after it
+    ///
+    /// Failures are no longer consecutive after `max_backoff` without one.
+    fn fail(&mut self, now: Instant, config: &PoolConfig) -> Duration {
+        let consecutive = self
+            .last_failure
+            .is_some_and(|last_failure| now - last_failure < config.max_backoff);
+        self.failures = if consecutive { self.failures + 1 } else { 1 };
+        self.last_failure = Some(now);
+
+        let backoff = config
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(self.failures - 1))
+            .min(config.max_backoff);
+        self.until = Some(now + backoff);
+        backoff
+    }
+
+    fn is_active(&self, now: Instant) -> bool {
+        self.until.is_some_and(|until| now < until)
+    }
+}
+
+#[derive(Debug)]
+struct Entry<C> {
+    client: Arc<C>,
+    last_used: Instant,
+    /// Connection failures, the client is re-initialized once the backoff has passed
+    reconnect: Backoff,
+    /// Overload errors, no calls are made to the node until the backoff has passed
+    overload: Backoff,
+}
+
+impl<C> Entry<C> {
+    fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            last_used: Instant::now(),
+            reconnect: Backoff::default(),
+            overload: Backoff::default(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ClientsPool<C: Client> {
-    clients: Arc<Mutex<HashMap<NodeId, Arc<C>>>>,
+    clients: Arc<Mutex<HashMap<NodeId, Entry<C>>>>,
+    /// Failures of the nodes without a client, which couldn't be initialized
+    unreachable: Mutex<HashMap<NodeId, Backoff>>,
+    config: ClientConfig,
 }
 
 impl<C: Client> Default for ClientsPool<C> {
     fn default() -> Self {
+        Self::new(ClientConfig::default())
+    }
+}
+
+impl<C: Client> ClientsPool<C> {
+    /// Create a pool initializing the clients with the given config
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The config passed to [`Client::init`], its `pool` field limits the pool
+    pub fn new(config: ClientConfig) -> Self {
         Self {
             clients: Arc::new(Mutex::new(HashMap::new())),
+            unreachable: Mutex::new(HashMap::new()),
+            config,
         }
     }
-}
 
-impl<C: Client> ClientsPool<C> {
     /// Get the client for the given node.
     /// If the client is not yet initialized, it will be initialized.
     ///
+    /// A client reported as failing is re-initialized once its backoff has passed,
+    /// until then the failing client is returned.
+    ///
+    /// A client which fails to initialize isn't kept, the error is returned instead. The failing
+    /// client it should have replaced backs off again. A node without a client backs off the
+    /// same way, [`ClientError::ConnectionFailed`] is returned without initializing a client
+    /// until its backoff has passed.
+    ///
     /// # Arguments
     ///
     /// * `node` - The node to get the client for
-    pub async fn get_or_init(&self, node: &Node) -> Arc<C> {
-        let client = {
-            let state = self.clients.lock().unwrap();
-            state.get(&node.id()).map(|c| c.clone())
+    pub async fn get_or_init(&self, node: &Node) -> Result<Arc<C>, ClientError> {
+        let now = Instant::now();
+        let (client, reconnect, overload) = {
+            let mut state = self.clients.lock().unwrap();
+            match state.get_mut(&node.id()) {
+                Some(entry) if entry.reconnect.until.is_none_or(|until| now < until) => {
+                    entry.last_used = now;
+                    return Ok(entry.client.clone());
+                }
+                Some(entry) => (Some(entry.client.clone()), entry.reconnect, entry.overload),
+                None => {
+                    let reconnect = self
+                        .unreachable
+                        .lock()
+                        .unwrap()
+                        .get(&node.id())
+                        .copied()
+                        .unwrap_or_default();
+                    if reconnect.is_active(now) {
+                        return Err(Report::new(ClientError::ConnectionFailed(format!(
+                            "Backing off from unreachable node {}",
+                            node.address()
+                        ))));
+                    }
+                    (None, reconnect, Backoff::default())
+                }
+            }
         };
 
         match client {
-            Some(c) => c,
-            None => {
-                log::debug!("Initializing client for node: {}", node.addr());
-                let client = C::init(node.addr()).await;
-                let client = Arc::new(client);
-                {
-                    let mut state = self.clients.lock().unwrap();
-                    state.insert(node.id(), client.clone());
+            Some(_) => log::debug!(
+                "Re-initializing failing client for node: {}",
+                node.address()
+            ),
+            None => log::debug!("Initializing client for node: {}", node.address()),
+        }
+        let client = match C::init(node.address(), self.config.clone()).await {
+            Ok(client) => Arc::new(client),
+            Err(report) => {
+                log::debug!("Failed to initialize client for node: {}", node.address());
+                match client {
+                    Some(_) => self.report_failure(node),
+                    None => self.report_unreachable(node),
                 }
-                client
+                return Err(report);
             }
+        };
+
+        self.unreachable.lock().unwrap().remove(&node.id());
+        let mut state = self.clients.lock().unwrap();
+        let mut entry = Entry::new(client.clone());
+        entry.reconnect = Backoff {
+            until: None,
+            ..reconnect
+        };
+        entry.overload = overload;
+        state.insert(node.id(), entry);
+        Self::evict_over_limit(&mut state, self.config.pool.max_size);
+
+        Ok(client)
+    }
+
+    /// Make a call with the client of the given node, see [`ClientsPool::get_or_init`]
+    ///
+    /// The call isn't made while the pool backs off from the node after it was overloaded,
+    /// [`ClientError::Overloaded`] is returned instead, see [`ClientsPool::report_overloaded`].
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node to call
+    /// * `call` - The call, made unless the client fails to initialize
+    pub async fn call<T, F, Fut>(&self, node: &Node, call: F) -> Result<T, ClientError>
+    where
+        F: FnOnce(Arc<C>) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let overloaded = self
+            .clients
+            .lock()
+            .unwrap()
+            .get(&node.id())
+            .is_some_and(|entry| entry.overload.is_active(Instant::now()));
+        if overloaded {
+            return Err(Report::new(ClientError::Overloaded(format!(
+                "Backing off from overloaded node {}",
+                node.addr()
+            ))));
+        }
+
+        call(self.get_or_init(node).await?).await
+    }
+
+    /// Report that the client of the given node failed to reach it
+    ///
+    /// The client is re-initialized after a backoff, which doubles with every consecutive
+    /// failure. Failures are no longer consecutive after `max_backoff` without one.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node which couldn't be reached
+    pub fn report_failure(&self, node: &Node) {
+        let mut state = self.clients.lock().unwrap();
+        let Some(entry) = state.get_mut(&node.id()) else {
+            return;
+        };
+
+        let backoff = entry.reconnect.fail(Instant::now(), &self.config.pool);
+        log::debug!(
+            "Client for node {} failed {} times, retrying in {:?}",
+            node.addr(),
+            entry.reconnect.failures,
+            backoff
+        );
+    }
+
+    /// Record that no client could be initialized for the given node, which has no client
+    ///
+    /// The nodes which didn't fail for `max_backoff` are forgotten.
+    fn report_unreachable(&self, node: &Node) {
+        let now = Instant::now();
+        let config = &self.config.pool;
+        let mut unreachable = self.unreachable.lock().unwrap();
+        unreachable.retain(|_, backoff| {
+            backoff
+                .last_failure
+                .is_some_and(|last_failure| now - last_failure < config.max_backoff)
+        });
+
+        let backoff = unreachable.entry(node.id()).or_default();
+        let delay = backoff.fail(now, config);
+        log::debug!(
+            "Node {} is unreachable, retrying in {:?}",
+            node.addr(),
+            delay
+        );
+    }
+
+    /// Report that the given node is overloaded
+    ///
+    /// No calls are made to the node for a backoff, which doubles with every consecutive
+    /// overload the same way as the backoff of [`ClientsPool::report_failure`].
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node which rejected a call as overloaded
+    pub fn report_overloaded(&self, node: &Node) {
+        let mut state = self.clients.lock().unwrap();
+        let Some(entry) = state.get_mut(&node.id()) else {
+            return;
+        };
+
+        let backoff = entry.overload.fail(Instant::now(), &self.config.pool);
+        log::debug!(
+            "Node {} is overloaded, backing off for {:?}",
+            node.addr(),
+            backoff
+        );
+    }
+
+    /// Report that a call made by the client of the given node failed
+    ///
+    /// Only the errors which mean the node can't be reached or is overloaded make the pool
+    /// back off from it, see [`ClientsPool::report_failure`] and
+    /// [`ClientsPool::report_overloaded`].
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node the call was made to
+    /// * `error` - The error of the call
+    pub fn report_error(&self, node: &Node, error: &ClientError) {
+        match error {
+            ClientError::ConnectionFailed(_) | ClientError::Timeout(_) => self.report_failure(node),
+            ClientError::Overloaded(_) => self.report_overloaded(node),
+            _ => {}
+        }
+    }
+
+    /// Remove the client of the given node, e.g. when the node is found dead
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node to remove the client for
+    pub fn remove(&self, node: &Node) {
+        self.clients.lock().unwrap().remove(&node.id());
+    }
+
+    /// Evict the clients which weren't used for the idle timeout
+    pub fn evict_idle(&self) {
+        let now = Instant::now();
+        let idle_timeout = self.config.pool.idle_timeout;
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|_, entry| now - entry.last_used < idle_timeout);
+    }
+
+    /// Get the number of clients in the pool
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Check if the pool is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn evict_over_limit(state: &mut HashMap<NodeId, Entry<C>>, max_size: usize) {
+        while state.len() > max_size.max(1) {
+            let least_recently_used = state
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| *id)
+                .unwrap();
+            state.remove(&least_recently_used);
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::net::SocketAddr;
+
+    use mockall::Sequence;
+
     use super::*;
     use crate::service::tests::MTX;
     use crate::Node;
     use crate::{client::MockClient, service::tests::get_lock};
 
+    fn pool(pool: PoolConfig) -> ClientsPool<MockClient> {
+        ClientsPool::new(ClientConfig {
+            pool,
+            ..ClientConfig::default()
+        })
+    }
+
     #[tokio::test]
     async fn test_getting_client() {
         let _m = get_lock(&MTX);
         let ctx = MockClient::init_context();
 
-        ctx.expect().returning(|_| MockClient::new());
+        ctx.expect().returning(|_, _| Ok(MockClient::new()));
 
         let node = Node::new("[::1]:42080".parse().unwrap());
 
@@ -69,18 +386,214 @@ mod tests {
             assert!(clients.is_empty());
         }
 
-        pool.get_or_init(&node).await;
+        pool.get_or_init(&node).await.unwrap();
         {
             let clients = pool.clients.lock().unwrap();
             assert_eq!(clients.len(), 1);
             assert!(clients.contains_key(&node.id()));
         }
 
-        pool.get_or_init(&node).await;
+        pool.get_or_init(&node).await.unwrap();
         {
             let clients = pool.clients.lock().unwrap();
             assert_eq!(clients.len(), 1);
             assert!(clients.contains_key(&node.id()));
         }
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn failing_clients_are_reinitialized_with_backoff() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+        ctx.expect()
+            .times(3)
+            .returning(|_, _| Ok(MockClient::new()));
+
+        let node = Node::new("[::1]:42080".parse().unwrap());
+        let pool = pool(PoolConfig {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            ..PoolConfig::default()
+        });
+        let client = pool.get_or_init(&node).await.unwrap();
+
+        pool.report_failure(&node);
+        assert!(Arc::ptr_eq(
+            &client,
+            &pool.get_or_init(&node).await.unwrap()
+        ));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let client = pool.get_or_init(&node).await.unwrap();
+
+        // The second consecutive failure doubles the backoff
+        pool.report_failure(&node);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(Arc::ptr_eq(
+            &client,
+            &pool.get_or_init(&node).await.unwrap()
+        ));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        pool.get_or_init(&node).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unreachable_nodes_are_initialized_with_backoff() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+        ctx.expect()
+            .times(2)
+            .returning(|_, _| Err(Report::new(ClientError::ConnectionFailed("down".into()))));
+
+        let node = Node::new("[::1]:42080".parse().unwrap());
+        let pool = pool(PoolConfig {
+            initial_backoff: Duration::from_secs(1),
+            ..PoolConfig::default()
+        });
+
+        assert!(pool.get_or_init(&node).await.is_err());
+        // No client is initialized until the backoff has passed
+        let report = pool.get_or_init(&node).await.unwrap_err();
+        assert!(matches!(
+            report.current_context(),
+            ClientError::ConnectionFailed(_)
+        ));
+        assert!(pool.is_empty());
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(pool.get_or_init(&node).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn calls_to_overloaded_nodes_back_off() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+        ctx.expect().times(1).returning(|_, _| {
+            let mut client = MockClient::new();
+            let mut seq = Sequence::new();
+            client
+                .expect_ping()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| Err(Report::new(ClientError::Overloaded("busy".into()))));
+            client
+                .expect_ping()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| Ok(()));
+            Ok(client)
+        });
+
+        let node = Node::new("[::1]:42080".parse().unwrap());
+        let pool = pool(PoolConfig {
+            initial_backoff: Duration::from_secs(1),
+            ..PoolConfig::default()
+        });
+        let ping = || pool.call(&node, |client| async move { client.ping().await });
+
+        let report = ping().await.unwrap_err();
+        pool.report_error(&node, report.current_context());
+
+        // The node isn't called until the backoff has passed
+        let report = ping().await.unwrap_err();
+        assert!(matches!(
+            report.current_context(),
+            ClientError::Overloaded(_)
+        ));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        ping().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clients_failing_to_initialize_are_not_kept() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+        let mut seq = Sequence::new();
+        ctx.expect()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Err(Report::new(ClientError::ConnectionFailed("fault".into()))));
+        ctx.expect()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(MockClient::new()));
+
+        let node = Node::new("[::1]:42080".parse().unwrap());
+        let pool: ClientsPool<MockClient> = ClientsPool::default();
+
+        assert!(pool.get_or_init(&node).await.is_err());
+        assert!(pool.is_empty());
+        tokio::time::advance(PoolConfig::default().initial_backoff).await;
+        pool.get_or_init(&node).await.unwrap();
+        assert_eq!(pool.clients.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn removed_clients_are_reinitialized() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+        ctx.expect()
+            .times(2)
+            .returning(|_, _| Ok(MockClient::new()));
+
+        let node = Node::new("[::1]:42080".parse().unwrap());
+        let pool: ClientsPool<MockClient> = ClientsPool::default();
+
+        pool.get_or_init(&node).await.unwrap();
+        pool.remove(&node);
+        assert!(pool.is_empty());
+        pool.get_or_init(&node).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn least_recently_used_clients_are_evicted_over_the_limit() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+        ctx.expect().returning(|_, _| Ok(MockClient::new()));
+
+        let nodes: Vec<Node> = (0..3)
+            .map(|i| Node::new(SocketAddr::from(([127, 0, 0, 1], 42080 + i))))
+            .collect();
+        let pool = pool(PoolConfig {
+            max_size: 2,
+            ..PoolConfig::default()
+        });
+
+        pool.get_or_init(&nodes[0]).await.unwrap();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        pool.get_or_init(&nodes[1]).await.unwrap();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        pool.get_or_init(&nodes[0]).await.unwrap();
+        tokio::time::advance(Duration::from_secs(1)).await;
+        pool.get_or_init(&nodes[2]).await.unwrap();
+
+        let clients = pool.clients.lock().unwrap();
+        assert_eq!(clients.len(), 2);
+        assert!(clients.contains_key(&nodes[0].id()));
+        assert!(clients.contains_key(&nodes[2].id()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_clients_are_evicted() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+        ctx.expect().returning(|_, _| Ok(MockClient::new()));
+
+        let nodes: Vec<Node> = (0..2)
+            .map(|i| Node::new(SocketAddr::from(([127, 0, 0, 1], 42080 + i))))
+            .collect();
+        let pool = pool(PoolConfig {
+            idle_timeout: Duration::from_secs(10),
+            ..PoolConfig::default()
+        });
+
+        pool.get_or_init(&nodes[0]).await.unwrap();
+        tokio::time::advance(Duration::from_secs(6)).await;
+        pool.get_or_init(&nodes[1]).await.unwrap();
+        tokio::time::advance(Duration::from_secs(6)).await;
+        pool.evict_idle();
+
+        let clients = pool.clients.lock().unwrap();
+        assert_eq!(clients.len(), 1);
+        assert!(clients.contains_key(&nodes[1].id()));
+    }
 }
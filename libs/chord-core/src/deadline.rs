@@ -0,0 +1,83 @@
+//! Deadlines propagated across the hops of a call
+//!
+//! A server runs the handler of a call within the deadline it received. The clients
+//! used by the handler cap their timeouts by the time left and send it along, so the
+//! hops of a lookup never exceed the budget of the original caller.
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Run the future within the given deadline
+///
+/// When the future already runs within a deadline, the earlier one is kept.
+///
+/// # Arguments
+///
+/// * `deadline` - The deadline, `None` to keep the current one
+/// * `f` - The future to run
+pub async fn scope<F: Future>(deadline: Option<Instant>, f: F) -> F::Output {
+    let deadline = match (deadline, current()) {
+        (Some(deadline), Some(current)) => Some(deadline.min(current)),
+        (deadline, current) => deadline.or(current),
+    };
+
+    match deadline {
+        Some(deadline) => DEADLINE.scope(deadline, f).await,
+        None => f.await,
+    }
+}
+
+/// Get the deadline the current task runs within
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Get the time a call can take, which is the timeout capped by the time left
+///
+/// # Arguments
+///
+/// * `timeout` - The timeout of the call
+pub fn budget(timeout: Duration) -> Duration {
+    match current() {
+        Some(deadline) => timeout.min(deadline.saturating_duration_since(Instant::now())),
+        None => timeout,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn budget_is_capped_by_the_earliest_deadline() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(timeout, budget(timeout));
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        scope(Some(deadline), async {
+            assert_eq!(Duration::from_secs(2), budget(timeout));
+
+            // A later deadline doesn't extend the budget
+            let later = Instant::now() + Duration::from_secs(3);
+            scope(Some(later), async {
+                tokio::time::advance(Duration::from_secs(1)).await;
+                assert_eq!(Duration::from_secs(1), budget(timeout));
+            })
+            .await;
+
+            scope(None, async {
+                assert_eq!(Some(deadline), current());
+            })
+            .await;
+        })
+        .await;
+
+        assert_eq!(None, current());
+    }
+}
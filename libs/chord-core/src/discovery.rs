@@ -0,0 +1,266 @@
+//! Discovery of the nodes over UDP multicast
+//!
+//! It's meant for the development clusters, where all nodes run on the same host or LAN. Every
+//! node announces its address to a multicast group. The node with the lowest address heard
+//! recently is the seed of the ring, every other node joins the ring of the seed once it hears
+//! the seed and the seed's ring doesn't route to it. So the nodes form a single ring without being
+//! told where it is, even when they start at the same time and first form rings of their own.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+
+use error_stack::Report;
+
+use crate::{error::ServiceError, Client, Node, NodeService};
+
+/// Prefix of the announcements, datagrams without it are ignored
+const MAGIC: &str = "chord-rs";
+
+/// Number of announcement intervals a node is remembered for after it was last heard. A node
+/// doesn't follow a seed until it listened for as long, so it has heard all of the running nodes.
+const HEARD_INTERVALS: u32 = 3;
+
+/// Configuration of the multicast discovery
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    /// The multicast group the nodes announce themselves to
+    pub group: SocketAddrV4,
+    /// The address of the interface to announce on,
+    /// the interface is chosen by the routing table when it's unspecified
+    pub interface: Ipv4Addr,
+    /// How often the node announces itself
+    pub interval: Duration,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            group: SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 99), 42999),
+            interface: Ipv4Addr::UNSPECIFIED,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Announce the node and join the ring of the seed, the node with the lowest address heard
+///
+/// The errors of the discovery are logged, the node keeps running without it. The discovery
+/// stops when the ring of the seed has a node with the same id.
+///
+/// # Arguments
+///
+/// * `node_service` - The node to announce
+/// * `config` - The configuration of the discovery
+pub fn spawn<T: Client + Clone + Sync + Send + 'static>(
+    node_service: Arc<NodeService<T>>,
+    config: DiscoveryConfig,
+) {
+    let socket = match socket(&config) {
+        Ok(socket) => Arc::new(socket),
+        Err(err) => {
+            log::error!("Failed to set up discovery on {}: {}", config.group, err);
+            return;
+        }
+    };
+    log::info!("Discovering nodes on {}", config.group);
+
+    let announcement = announcement(node_service.addr());
+    let sender = socket.clone();
+    let announcer = tokio::spawn(async move {
+        loop {
+            if let Err(err) = sender.send_to(announcement.as_bytes(), config.group).await {
+                log::warn!("Failed to announce node on {}: {}", config.group, err);
+            }
+            tokio::time::sleep(config.interval).await;
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut buf = [0; 128];
+        let mut peers = Peers::new(
+            node_service.addr(),
+            config.interval * HEARD_INTERVALS,
+            Instant::now(),
+        );
+        loop {
+            let peer = match socket.recv_from(&mut buf).await {
+                Ok((len, _)) => parse_announcement(&buf[..len]),
+                Err(err) => {
+                    log::warn!("Failed to receive announcement: {}", err);
+                    None
+                }
+            };
+            let Some(peer) = peer else {
+                continue;
+            };
+
+            let now = Instant::now();
+            peers.heard(peer, now);
+            // The seed is followed as it announces itself, i.e. once per interval
+            if peer == peers.seed(now) && peers.settled(now) && peer != node_service.addr() {
+                if let Err(report) = follow(&node_service, Node::new(peer)).await {
+                    log::error!("Stopping discovery: {:?}", report);
+                    announcer.abort();
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Join the ring of the seed, unless the node is already a member of it
+///
+/// Fails only when the ring of the seed has a node with the same id, other errors are logged.
+async fn follow<T: Client + Clone + Sync + Send + 'static>(
+    node_service: &NodeService<T>,
+    seed: Node,
+) -> Result<(), Report<ServiceError>> {
+    match node_service.shares_ring_with(&seed).await {
+        Ok(true) => return Ok(()),
+        Ok(false) => log::info!("Discovered seed {}, joining its ring", seed.addr),
+        Err(report) => {
+            log::warn!("Failed to reach seed {}: {:?}", seed.addr, report);
+            return Ok(());
+        }
+    }
+
+    match node_service.join(seed.clone()).await {
+        Err(report) if report.current_context() == &ServiceError::DuplicateId => Err(report),
+        Err(report) => {
+            log::warn!("Failed to join ring through {}: {:?}", seed.addr, report);
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+/// The nodes heard recently
+#[derive(Debug)]
+struct Peers {
+    /// The address of the node itself
+    own: SocketAddr,
+    /// When each node was last heard
+    heard: HashMap<SocketAddr, Instant>,
+    /// How long a node is remembered for after it was last heard
+    ttl: Duration,
+    /// When the node started listening
+    started: Instant,
+}
+
+impl Peers {
+    fn new(own: SocketAddr, ttl: Duration, started: Instant) -> Self {
+        Self {
+            own,
+            heard: HashMap::new(),
+            ttl,
+            started,
+        }
+    }
+
+    fn heard(&mut self, addr: SocketAddr, now: Instant) {
+        self.heard.insert(addr, now);
+    }
+
+    /// Get the lowest address of the node and the nodes heard within the ttl
+    fn seed(&mut self, now: Instant) -> SocketAddr {
+        self.heard
+            .retain(|_, heard| now.saturating_duration_since(*heard) <= self.ttl);
+        self.heard
+            .keys()
+            .copied()
+            .chain(std::iter::once(self.own))
+            .min()
+            .unwrap_or(self.own)
+    }
+
+    /// Check if the node listened long enough to have heard all of the running nodes
+    fn settled(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.started) >= self.ttl
+    }
+}
+
+fn socket(config: &DiscoveryConfig) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // All nodes of the host listen on the port of the group
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, config.group.port())).into())?;
+    socket.join_multicast_v4(config.group.ip(), &config.interface)?;
+    socket.set_multicast_if_v4(&config.interface)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+fn announcement(addr: SocketAddr) -> String {
+    format!("{} {}", MAGIC, addr)
+}
+
+fn parse_announcement(datagram: &[u8]) -> Option<SocketAddr> {
+    let (magic, addr) = std::str::from_utf8(datagram).ok()?.split_once(' ')?;
+    if magic != MAGIC {
+        return None;
+    }
+
+    let addr: SocketAddr = addr.parse().ok()?;
+    Node::new(addr).validate().ok()?;
+
+    Some(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_announcement() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 42001));
+        assert_eq!(
+            parse_announcement(announcement(addr).as_bytes()),
+            Some(addr)
+        );
+
+        let addr: SocketAddr = "[::1]:42001".parse().unwrap();
+        assert_eq!(
+            parse_announcement(announcement(addr).as_bytes()),
+            Some(addr)
+        );
+    }
+
+    #[test]
+    fn test_seed_is_the_lowest_address_heard_recently() {
+        let addr = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let start = Instant::now();
+        let ttl = Duration::from_secs(3);
+        let mut peers = Peers::new(addr(42002), ttl, start);
+        assert_eq!(peers.seed(start), addr(42002));
+        assert!(!peers.settled(start));
+
+        peers.heard(addr(42003), start);
+        assert_eq!(peers.seed(start), addr(42002));
+
+        peers.heard(addr(42001), start);
+        assert_eq!(peers.seed(start + ttl), addr(42001));
+        assert!(peers.settled(start + ttl));
+
+        // A node which is no longer heard stops being the seed
+        peers.heard(addr(42003), start + ttl);
+        assert_eq!(peers.seed(start + ttl * 2), addr(42002));
+        assert_eq!(peers.heard.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_invalid_announcement() {
+        assert_eq!(parse_announcement(b"chord-rs"), None);
+        assert_eq!(parse_announcement(b"chord-rs 127.0.0.1"), None);
+        assert_eq!(parse_announcement(b"other 127.0.0.1:42001"), None);
+        assert_eq!(parse_announcement(b"chord-rs 127.0.0.1:0"), None);
+        assert_eq!(parse_announcement(&[0xff, 0xfe]), None);
+    }
+}
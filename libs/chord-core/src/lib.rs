@@ -1,14 +1,22 @@
+pub mod auth;
 pub mod client;
+pub mod deadline;
+pub mod discovery;
+pub mod lookup;
 mod node;
 pub mod server;
 mod service;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 use seahash::hash;
 use std::fmt::Display;
 use std::net::SocketAddr;
+use std::str::FromStr;
+use thiserror::Error;
 
 pub use client::Client;
-pub use service::NodeService;
+pub use service::{FixFingers, NodeService, ServiceConfig};
 
 pub use service::error;
 
@@ -21,6 +29,13 @@ impl From<SocketAddr> for NodeId {
     }
 }
 
+/// The id of a node reached by the address, a host name keeps the id when its address changes
+impl From<&Address> for NodeId {
+    fn from(addr: &Address) -> Self {
+        Self(hash(addr.to_string().as_bytes()))
+    }
+}
+
 impl From<String> for NodeId {
     fn from(key: String) -> Self {
         Self(hash(key.as_bytes()))
@@ -39,17 +54,88 @@ impl From<u64> for NodeId {
     }
 }
 
+impl NodeId {
+    /// Get the id of a node named by the given name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the node, it's hashed the same way as the keys
+    pub fn from_name(name: &str) -> Self {
+        Self(hash(name.as_bytes()))
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid node id '{0}', expected a decimal or a 0x prefixed hex number")]
+pub struct InvalidNodeId(String);
+
+impl FromStr for NodeId {
+    type Err = InvalidNodeId;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let id = match value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("0X"))
+        {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => value.parse(),
+        };
+
+        id.map(Self).map_err(|_| InvalidNodeId(value.to_string()))
+    }
+}
+
 impl Display for NodeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
+/// The address a client connects to
+///
+/// A host name is resolved when the client connects, so a node keeps being reachable when
+/// its name moves to another address.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Address {
+    Socket(SocketAddr),
+    Host(String, u16),
+}
+
+impl Address {
+    pub fn port(&self) -> u16 {
+        match self {
+            Address::Socket(addr) => addr.port(),
+            Address::Host(_, port) => *port,
+        }
+    }
+}
+
+impl From<SocketAddr> for Address {
+    fn from(addr: SocketAddr) -> Self {
+        Address::Socket(addr)
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Socket(addr) => write!(f, "{}", addr),
+            Address::Host(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid node: {0}")]
+pub struct InvalidNode(String);
+
 /// A reference to a node in the chord ring
 #[derive(Clone, PartialEq, Debug)]
 pub struct Node {
     id: NodeId,
     addr: SocketAddr,
+    /// The host name the node is reached by, with the port of `addr`
+    host: Option<String>,
 }
 
 impl Node {
@@ -57,6 +143,7 @@ impl Node {
         Self {
             id: addr.into(),
             addr,
+            host: None,
         }
     }
 
@@ -68,13 +155,71 @@ impl Node {
         Self {
             id: id.into(),
             addr,
+            host: None,
         }
     }
 
+    /// Set the host name the node is reached by
+    ///
+    /// The clients connect to the addresses the host name resolves to, `addr` is then
+    /// the address the node had when it was announced.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The host name, `None` when the node is reached by its address
+    pub fn with_host(mut self, host: Option<String>) -> Self {
+        self.host = host;
+        self
+    }
+
     pub fn id(&self) -> NodeId {
         self.id
     }
 
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// Get the address the clients connect to the node on, its host name when it has one
+    pub fn address(&self) -> Address {
+        match &self.host {
+            Some(host) => Address::Host(host.clone(), self.addr.port()),
+            None => Address::Socket(self.addr),
+        }
+    }
+
+    /// Check that a node received from another node can be connected to
+    ///
+    /// The port can't be 0 and the host name has to be a DNS name, made of labels of letters,
+    /// digits and hyphens.
+    ///
+    /// The id isn't checked against the hash of the address or the host name. A node can be
+    /// given any id, and it keeps its id in its data directory when it moves to another address,
+    /// so a valid id can't be told from an inconsistent one. Duplicate ids are detected when a
+    /// node joins the ring instead, see [`NodeService::join`].
+    pub fn validate(&self) -> Result<(), InvalidNode> {
+        if self.addr.port() == 0 {
+            return Err(InvalidNode(format!("port 0 of {}", self.addr)));
+        }
+
+        if let Some(host) = &self.host {
+            let name = host.strip_suffix('.').unwrap_or(host);
+            let valid_label = |label: &str| {
+                (1..=63).contains(&label.len())
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            };
+            if name.len() > 253 || !name.split('.').all(valid_label) {
+                return Err(InvalidNode(format!("host name '{}'", host)));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Returns true if the given id is between 2 nodes on a ring
     ///
     /// # Arguments
@@ -128,6 +273,54 @@ impl Node {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_node_id() {
+        assert_eq!("42".parse::<NodeId>().unwrap(), NodeId(42));
+        assert_eq!("0x2a".parse::<NodeId>().unwrap(), NodeId(42));
+        assert_eq!("0XFF".parse::<NodeId>().unwrap(), NodeId(255));
+        assert!("-1".parse::<NodeId>().is_err());
+        assert!("0xfoo".parse::<NodeId>().is_err());
+        assert!("node".parse::<NodeId>().is_err());
+    }
+
+    #[test]
+    fn test_address_of_node() {
+        let addr = SocketAddr::from(([10, 0, 0, 1], 42000));
+        assert_eq!(Node::new(addr).address(), Address::Socket(addr));
+
+        let node = Node::new(addr).with_host(Some("chord-0.chord".to_string()));
+        assert_eq!(
+            node.address(),
+            Address::Host("chord-0.chord".to_string(), 42000)
+        );
+        assert_eq!(node.address().to_string(), "chord-0.chord:42000");
+    }
+
+    #[test]
+    fn test_validate_node() {
+        let addr = SocketAddr::from(([10, 0, 0, 1], 42000));
+        let with_host = |host: &str| Node::new(addr).with_host(Some(host.to_string()));
+
+        assert!(Node::new(addr).validate().is_ok());
+        assert!(with_host("chord-0.chord").validate().is_ok());
+        assert!(with_host("chord-0.chord.").validate().is_ok());
+
+        assert!(Node::new(SocketAddr::from(([10, 0, 0, 1], 0)))
+            .validate()
+            .is_err());
+        for host in [
+            "",
+            "chord 0",
+            "chord-0:42000",
+            "-chord",
+            "chord..0",
+            "chord/0",
+        ] {
+            assert!(with_host(host).validate().is_err(), "{}", host);
+        }
+        assert!(with_host(&"a".repeat(64)).validate().is_err());
+    }
+
     #[test]
     fn test_is_between() {
         assert_eq!(Node::is_between_on_ring(10, 5, 5), true);
@@ -0,0 +1,314 @@
+//! Cache of lookup results
+//!
+//! A lookup of an id doesn't only tell who owns the id. The owner is the successor of the last
+//! hop, so it owns every id between the last hop and itself. The cache keeps these ranges, so
+//! that the ids which are looked up repeatedly are resolved without routing.
+//!
+//! Entries go stale when nodes join or leave the ring. The users of the cache invalidate the
+//! owners which fail and record the owners they are redirected to, which shrinks the ranges the
+//! new owners are known to take over. Entries also expire after a while.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{Node, NodeId};
+
+/// Limits of the [`LookupCache`]
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Maximum number of cached ranges, the least recently used ones are evicted over it.
+    /// The cache is disabled when it's zero.
+    pub capacity: usize,
+    /// Ranges which were looked up this long ago are not used anymore
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            ttl: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// The id preceding the range, it's not a part of it
+    start: u64,
+    owner: Node,
+    expires_at: Instant,
+    last_used: Instant,
+}
+
+impl Entry {
+    /// The number of ids in the range, minus one so that the whole ring fits
+    fn len(&self) -> u64 {
+        range_len(self.start, self.owner.id.0)
+    }
+}
+
+/// Bounded cache mapping ranges of ids to the nodes which own them
+#[derive(Debug)]
+pub struct LookupCache {
+    /// The cached ranges by the id of their owner, which is also the end of the range
+    entries: Mutex<BTreeMap<u64, Entry>>,
+    config: CacheConfig,
+}
+
+impl Default for LookupCache {
+    fn default() -> Self {
+        Self::new(CacheConfig::default())
+    }
+}
+
+impl LookupCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+            config,
+        }
+    }
+
+    /// Get the cached owner of the given id
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id to find the owner for
+    pub fn get(&self, id: NodeId) -> Option<Node> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        // The first owner clockwise from the id is the only one which can own it
+        let end = entries
+            .range(id.0..)
+            .next()
+            .or_else(|| entries.iter().next())
+            .map(|(end, _)| *end)?;
+
+        let entry = entries.get_mut(&end).unwrap();
+        if now >= entry.expires_at {
+            entries.remove(&end);
+            return None;
+        }
+        if !Node::is_between_on_ring(id.0, entry.start, end) {
+            return None;
+        }
+
+        entry.last_used = now;
+        Some(entry.owner.clone())
+    }
+
+    /// Record that the given node owns the ids from `from` up to its own id
+    ///
+    /// The cached ranges the new one contradicts are shrunk or removed. A node within a cached
+    /// range means the range's owner no longer owns the ids up to the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The first id of the range
+    /// * `owner` - The node which owns the range
+    pub fn insert(&self, from: NodeId, owner: Node) {
+        if self.config.capacity == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let start = from.0.wrapping_sub(1);
+        let end = owner.id.0;
+        let mut entries = self.entries.lock().unwrap();
+
+        entries
+            .retain(|key, _| *key == end || !Node::is_between_on_ring_exclusive(*key, start, end));
+        for (key, entry) in entries.iter_mut() {
+            if *key != end && Node::is_between_on_ring(end, entry.start, *key) {
+                entry.start = end;
+            }
+        }
+
+        let expires_at = now + self.config.ttl;
+        match entries.get_mut(&end) {
+            // Both ranges end at the owner, so the longer one contains the other
+            Some(entry) => {
+                if range_len(start, end) > entry.len() {
+                    entry.start = start;
+                }
+                entry.owner = owner;
+                entry.expires_at = expires_at;
+                entry.last_used = now;
+            }
+            None => {
+                entries.insert(
+                    end,
+                    Entry {
+                        start,
+                        owner,
+                        expires_at,
+                        last_used: now,
+                    },
+                );
+            }
+        }
+
+        if entries.len() > self.config.capacity {
+            entries.retain(|_, entry| now < entry.expires_at);
+        }
+        while entries.len() > self.config.capacity {
+            let least_recently_used = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(end, _)| *end)
+                .unwrap();
+            entries.remove(&least_recently_used);
+        }
+    }
+
+    /// Remove the ranges owned by the given node, e.g. when a request to it fails
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node which is no longer known to own its ranges
+    pub fn invalidate(&self, node: &Node) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, entry| entry.owner.id != node.id);
+    }
+
+    /// Remove all the cached ranges
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Get the number of cached ranges
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Check if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The number of ids in the range `(start, end]`, minus one
+fn range_len(start: u64, end: u64) -> u64 {
+    end.wrapping_sub(start).wrapping_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    fn node(id: u64) -> Node {
+        Node::with_id(id, SocketAddr::from(([127, 0, 0, 1], 42000 + id as u16)))
+    }
+
+    fn cache(capacity: usize) -> LookupCache {
+        LookupCache::new(CacheConfig {
+            capacity,
+            ..CacheConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_get_within_range() {
+        let cache = cache(8);
+        cache.insert(NodeId(10), node(16));
+
+        assert_eq!(cache.get(NodeId(9)), None);
+        assert_eq!(cache.get(NodeId(10)), Some(node(16)));
+        assert_eq!(cache.get(NodeId(16)), Some(node(16)));
+        assert_eq!(cache.get(NodeId(17)), None);
+    }
+
+    #[test]
+    fn test_get_range_wrapping_around_the_ring() {
+        let cache = cache(8);
+        cache.insert(NodeId(u64::MAX - 2), node(4));
+
+        assert_eq!(cache.get(NodeId(u64::MAX)), Some(node(4)));
+        assert_eq!(cache.get(NodeId(0)), Some(node(4)));
+        assert_eq!(cache.get(NodeId(5)), None);
+    }
+
+    #[test]
+    fn test_insert_keeps_the_longer_range_of_an_owner() {
+        let cache = cache(8);
+        cache.insert(NodeId(8), node(16));
+        cache.insert(NodeId(12), node(16));
+
+        assert_eq!(cache.get(NodeId(8)), Some(node(16)));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_insert_shrinks_the_contradicted_ranges() {
+        let cache = cache(8);
+        cache.insert(NodeId(8), node(32));
+        cache.insert(NodeId(40), node(48));
+
+        // A node joined between 8 and 32
+        cache.insert(NodeId(20), node(24));
+
+        assert_eq!(cache.get(NodeId(12)), None);
+        assert_eq!(cache.get(NodeId(22)), Some(node(24)));
+        assert_eq!(cache.get(NodeId(28)), Some(node(32)));
+
+        // The range of 32 turns out to reach past 40
+        cache.insert(NodeId(33), node(64));
+        assert_eq!(cache.get(NodeId(44)), Some(node(64)));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let cache = cache(8);
+        cache.insert(NodeId(8), node(16));
+        cache.insert(NodeId(20), node(24));
+
+        cache.invalidate(&node(16));
+
+        assert_eq!(cache.get(NodeId(10)), None);
+        assert_eq!(cache.get(NodeId(22)), Some(node(24)));
+    }
+
+    #[test]
+    fn test_evicts_the_least_recently_used_range() {
+        let cache = cache(2);
+        cache.insert(NodeId(8), node(16));
+        cache.insert(NodeId(20), node(24));
+        std::thread::sleep(Duration::from_millis(1));
+        cache.get(NodeId(10));
+
+        cache.insert(NodeId(30), node(32));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(NodeId(10)), Some(node(16)));
+        assert_eq!(cache.get(NodeId(22)), None);
+    }
+
+    #[test]
+    fn test_expired_range_is_not_used() {
+        let cache = LookupCache::new(CacheConfig {
+            capacity: 8,
+            ttl: Duration::ZERO,
+        });
+        cache.insert(NodeId(8), node(16));
+
+        assert_eq!(cache.get(NodeId(10)), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_disabled_cache() {
+        let cache = cache(0);
+        cache.insert(NodeId(8), node(16));
+
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+//! Iterative lookups
+//!
+//! In a recursive lookup every node forwards the query to its closest preceding node and waits
+//! for the answer. In an iterative lookup the querying node contacts each hop itself: it asks the
+//! hop for its successor and its closest finger preceding the id, then moves on to the finger
+//! until the id falls between a hop and its successor. The querying node sees every hop, so it
+//! can route around the hops which fail and report the path of the lookup.
+
+use std::time::Duration;
+
+use error_stack::{Report, Result};
+use tokio::time::Instant;
+
+use crate::client::{ClientError, ClientsPool};
+use crate::{deadline, Client, Node, NodeId};
+
+pub mod cache;
+pub mod resolver;
+
+pub use cache::{CacheConfig, LookupCache};
+pub use resolver::Resolver;
+
+/// Routing information a node returns for a step of an iterative lookup
+#[derive(Debug, Clone, PartialEq)]
+pub struct NextHop {
+    /// The node which answered, it tells the id of a node known only by its address
+    pub node: Node,
+    /// The successor of the node
+    pub successor: Node,
+    /// The closest finger of the node preceding the id, `None` when no finger precedes it
+    pub finger: Option<PrecedingFinger>,
+}
+
+/// A finger preceding the id of a lookup
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrecedingFinger {
+    /// The index of the finger in the finger table
+    pub index: u8,
+    pub node: Node,
+}
+
+/// A node visited by a lookup
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hop {
+    pub node: Node,
+    /// The finger of the previous hop which pointed to the node,
+    /// `None` when the node is the first hop or the successor of the previous hop
+    pub finger: Option<u8>,
+    /// The time it took the node to respond
+    pub latency: Duration,
+}
+
+/// The result of an iterative lookup
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lookup {
+    /// The node responsible for the id
+    pub owner: Node,
+    /// The nodes visited by the lookup, in order
+    pub path: Vec<Hop>,
+}
+
+impl Lookup {
+    /// Look up the successor of the given id, starting at the given node
+    ///
+    /// # Arguments
+    ///
+    /// * `clients` - The pool of the clients used to contact the hops
+    /// * `id` - The id to find the successor for
+    /// * `start` - The node to ask first, only its address is used
+    pub async fn run<C: Client>(
+        clients: &ClientsPool<C>,
+        id: NodeId,
+        start: Node,
+    ) -> Result<Self, ClientError> {
+        let started = Instant::now();
+        let next = clients
+            .call(&start, |client| async move { client.next_hop(id).await })
+            .await
+            .inspect_err(|report| clients.report_error(&start, report.current_context()))?;
+        // The id of the start node can't be derived from its address
+        let hop = Hop {
+            node: next.node.clone(),
+            finger: None,
+            latency: started.elapsed(),
+        };
+
+        Self::resume(clients, id, hop, next).await
+    }
+
+    /// Continue a lookup from a hop which already responded
+    ///
+    /// Each step moves to the closest preceding finger of the current hop, or to its successor
+    /// when the finger fails to respond. Both always precede the id, so the lookup makes
+    /// progress with every hop.
+    pub(crate) async fn resume<C: Client>(
+        clients: &ClientsPool<C>,
+        id: NodeId,
+        hop: Hop,
+        mut next: NextHop,
+    ) -> Result<Self, ClientError> {
+        let mut path = vec![hop];
+        loop {
+            let current = path.last().unwrap().node.clone();
+            if Node::is_between_on_ring(id.0, current.id.0, next.successor.id.0) {
+                return Ok(Self {
+                    owner: next.successor,
+                    path,
+                });
+            }
+
+            let mut candidates = Vec::with_capacity(2);
+            if let Some(finger) = next.finger {
+                candidates.push((Some(finger.index), finger.node));
+            }
+            if !candidates.iter().any(|(_, node)| *node == next.successor) {
+                candidates.push((None, next.successor));
+            }
+            candidates.retain(|(_, node)| {
+                Node::is_between_on_ring_exclusive(node.id.0, current.id.0, id.0)
+            });
+
+            let mut failure = None;
+            let mut step = None;
+            for (finger, node) in candidates {
+                let started = Instant::now();
+                let result = clients
+                    .call(&node, |client| async move { client.next_hop(id).await })
+                    .await;
+                match result {
+                    Ok(next) => {
+                        let latency = started.elapsed();
+                        step = Some((
+                            Hop {
+                                node,
+                                finger,
+                                latency,
+                            },
+                            next,
+                        ));
+                        break;
+                    }
+                    Err(report) if can_route_around(report.current_context()) => {
+                        log::debug!("Hop {:?} failed: {:?}", node.addr, report);
+                        clients.report_error(&node, report.current_context());
+                        failure = Some(report);
+                    }
+                    Err(report) => {
+                        clients.report_error(&node, report.current_context());
+                        return Err(report);
+                    }
+                }
+            }
+
+            match (step, failure) {
+                (Some((hop, hop_next)), _) => {
+                    path.push(hop);
+                    next = hop_next;
+                }
+                (None, Some(report)) => return Err(report),
+                (None, None) => {
+                    return Err(
+                        Report::new(ClientError::Unexpected).attach_printable(format!(
+                            "No hop of {:?} makes progress towards id '{}'",
+                            current.addr, id
+                        )),
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Check if a lookup can continue with another hop after the error
+fn can_route_around(error: &ClientError) -> bool {
+    match error {
+        // An overloaded node is backed off from, another hop spreads the load
+        ClientError::ConnectionFailed(_) | ClientError::Overloaded(_) => true,
+        // A hung node is routed around as long as the deadline allows it
+        ClientError::Timeout(_) => !deadline::budget(Duration::MAX).is_zero(),
+        _ => false,
+    }
+}
@@ -0,0 +1,168 @@
+use error_stack::Result;
+
+use crate::client::{ClientConfig, ClientError, ClientsPool};
+use crate::{Client, Node, NodeId};
+
+use super::{CacheConfig, LookupCache};
+
+/// Resolves ids to the nodes which own them, for the applications using the ring
+///
+/// The owners are looked up through a node of the ring and cached, see [`LookupCache`].
+/// The application reports the requests to an owner which fail or are redirected to another
+/// node, so that the stale owner isn't used again.
+#[derive(Debug)]
+pub struct Resolver<C: Client> {
+    /// The node of the ring the lookups are sent to
+    entry: Node,
+    clients: ClientsPool<C>,
+    cache: LookupCache,
+}
+
+impl<C: Client> Resolver<C> {
+    /// Create a resolver looking up the owners through the given node
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The node of the ring the lookups are sent to
+    /// * `config` - The config of the clients
+    /// * `cache` - The limits of the cache of the owners
+    pub fn new(entry: Node, config: ClientConfig, cache: CacheConfig) -> Self {
+        Self {
+            entry,
+            clients: ClientsPool::new(config),
+            cache: LookupCache::new(cache),
+        }
+    }
+
+    /// Get the node which owns the given id
+    ///
+    /// The cached owner is returned when there's one, otherwise the owner is looked up.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id to find the owner for
+    pub async fn owner(&self, id: NodeId) -> Result<Node, ClientError> {
+        if let Some(owner) = self.cache.get(id) {
+            return Ok(owner);
+        }
+
+        let owner = self
+            .clients
+            .call(&self.entry, |client| async move {
+                client.find_successor(id).await
+            })
+            .await
+            .inspect_err(|report| {
+                self.clients
+                    .report_error(&self.entry, report.current_context())
+            })?;
+        self.cache.insert(id, owner.clone());
+
+        Ok(owner)
+    }
+
+    /// Report that a request to the owner failed, the owner is looked up again next time
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The node the request was sent to
+    pub fn failed(&self, owner: &Node) {
+        self.cache.invalidate(owner);
+    }
+
+    /// Report that the owner redirected a request for the given id to another node
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the request
+    /// * `owner` - The node the request was sent to
+    /// * `redirect` - The node the request was redirected to, which owns the id
+    pub fn redirected(&self, id: NodeId, owner: &Node, redirect: Node) {
+        self.cache.invalidate(owner);
+        self.cache.insert(id, redirect);
+    }
+
+    /// Get the cache of the owners
+    pub fn cache(&self) -> &LookupCache {
+        &self.cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::client::MockClient;
+    use crate::service::tests::{get_lock, MTX};
+
+    fn node(id: u64) -> Node {
+        Node::with_id(id, SocketAddr::from(([127, 0, 0, 1], 42000 + id as u16)))
+    }
+
+    #[tokio::test]
+    async fn test_owner_is_looked_up_once() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+
+        ctx.expect().returning(|_, _| {
+            let mut client = MockClient::new();
+            client
+                .expect_find_successor()
+                .times(1)
+                .returning(|_| Ok(node(16)));
+            Ok(client)
+        });
+
+        let resolver: Resolver<MockClient> =
+            Resolver::new(node(8), ClientConfig::default(), CacheConfig::default());
+
+        assert_eq!(resolver.owner(NodeId(12)).await.unwrap(), node(16));
+        assert_eq!(resolver.owner(NodeId(12)).await.unwrap(), node(16));
+    }
+
+    #[tokio::test]
+    async fn test_owner_is_looked_up_again_after_a_failure() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+
+        ctx.expect().returning(|_, _| {
+            let mut client = MockClient::new();
+            client
+                .expect_find_successor()
+                .times(2)
+                .returning(|_| Ok(node(16)));
+            Ok(client)
+        });
+
+        let resolver: Resolver<MockClient> =
+            Resolver::new(node(8), ClientConfig::default(), CacheConfig::default());
+
+        let owner = resolver.owner(NodeId(12)).await.unwrap();
+        resolver.failed(&owner);
+        resolver.owner(NodeId(12)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redirect_replaces_the_owner() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+
+        ctx.expect().returning(|_, _| {
+            let mut client = MockClient::new();
+            client
+                .expect_find_successor()
+                .times(1)
+                .returning(|_| Ok(node(16)));
+            Ok(client)
+        });
+
+        let resolver: Resolver<MockClient> =
+            Resolver::new(node(8), ClientConfig::default(), CacheConfig::default());
+
+        let owner = resolver.owner(NodeId(12)).await.unwrap();
+        resolver.redirected(NodeId(12), &owner, node(14));
+
+        assert_eq!(resolver.owner(NodeId(12)).await.unwrap(), node(14));
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use crate::Node;
 
 /// Finger table entry
@@ -5,12 +7,60 @@ use crate::Node;
 pub struct Finger {
     pub(crate) _start: u64,
     pub node: Node,
+    /// Nodes from the interval of the finger which can be used instead of its node,
+    /// starting with the node itself
+    pub candidates: Vec<Candidate>,
+}
+
+/// A node which can serve as a finger
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub node: Node,
+    /// Smoothed round trip time to the node, `None` until it's measured
+    pub rtt: Option<Duration>,
+}
+
+impl Candidate {
+    pub(crate) fn new(node: Node) -> Self {
+        Self { node, rtt: None }
+    }
+
+    /// Cost of routing through the node, unmeasured nodes are the most expensive
+    pub(crate) fn cost(&self) -> Duration {
+        self.rtt.unwrap_or(Duration::MAX)
+    }
+
+    /// Add a round trip time sample to the smoothed one
+    pub(crate) fn record_rtt(&mut self, sample: Duration) {
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => (rtt * 7 + sample) / 8,
+            None => sample,
+        });
+    }
 }
 
 impl Finger {
     /// Finger table size
     pub const FINGER_TABLE_SIZE: u8 = 64;
 
+    /// Maximum number of candidates kept for a finger, including its node
+    pub const MAX_CANDIDATES: usize = 3;
+
+    /// Check if the given id is in the interval of the finger at the given index,
+    /// i.e. between its start and the start of the next finger
+    ///
+    /// # Arguments
+    ///
+    /// * `node_id` - The id of the node owning the finger table
+    /// * `index` - The index of the finger
+    /// * `id` - The id to check
+    pub(crate) fn in_interval(node_id: u64, index: usize, id: u64) -> bool {
+        let start = Self::finger_id(node_id, (index + 1) as u8);
+        let end = Self::finger_id(node_id, (index + 2) as u8);
+
+        Node::is_between_on_ring(id, start.wrapping_sub(1), end.wrapping_sub(1))
+    }
+
     /// Generate a finger id for a given node id and finger index.
     /// The finger id is calculated using the following formula:
     /// ```text
@@ -62,6 +112,7 @@ impl Finger {
             fingers.push(Finger {
                 _start: finger_id,
                 node: node.clone(),
+                candidates: vec![Candidate::new(node.clone())],
             });
         }
 
@@ -75,6 +126,7 @@ mod tests {
 
     use super::*;
     use std::net::SocketAddr;
+    use std::time::Duration;
 
     #[test]
     fn it_should_generate_finger_id() {
@@ -111,6 +163,33 @@ mod tests {
         assert_eq!(Finger::sized_finger_id(M, node_id, 7), 1);
     }
 
+    #[test]
+    fn it_should_check_finger_interval() {
+        let node_id: u64 = 1;
+
+        assert!(Finger::in_interval(node_id, 0, 2));
+        assert!(!Finger::in_interval(node_id, 0, 3));
+        assert!(Finger::in_interval(node_id, 4, 17));
+        assert!(Finger::in_interval(node_id, 4, 32));
+        assert!(!Finger::in_interval(node_id, 4, 33));
+        assert!(Finger::in_interval(node_id, 63, u64::MAX));
+        assert!(Finger::in_interval(node_id, 63, 0));
+        assert!(!Finger::in_interval(node_id, 63, 1));
+    }
+
+    #[test]
+    fn it_should_smooth_rtt() {
+        let node = Node::with_id(NodeId(1), SocketAddr::from(([127, 0, 0, 1], 42001)));
+        let mut candidate = Candidate::new(node);
+        assert_eq!(candidate.cost(), Duration::MAX);
+
+        candidate.record_rtt(Duration::from_millis(80));
+        assert_eq!(candidate.rtt, Some(Duration::from_millis(80)));
+
+        candidate.record_rtt(Duration::from_millis(0));
+        assert_eq!(candidate.rtt, Some(Duration::from_millis(70)));
+    }
+
     #[test]
     fn it_should_generate_finger_table() {
         let node = Node::with_id(NodeId(1), SocketAddr::from(([127, 0, 0, 1], 42001)));
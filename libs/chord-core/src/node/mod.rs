@@ -2,4 +2,4 @@ pub(crate) mod store;
 
 mod finger;
 
-pub(super) use finger::Finger;
+pub(super) use finger::{Candidate, Finger};
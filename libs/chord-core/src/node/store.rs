@@ -1,6 +1,7 @@
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::node::Finger;
+use crate::node::{Candidate, Finger};
 use crate::Node;
 
 /// A node in the chord ring
@@ -165,27 +166,115 @@ impl Db {
     ///
     /// The closest preceding node for the key
     pub(crate) fn closest_preceding_node(&self, node_id: u64, id: u64) -> Option<Node> {
+        self.closest_preceding_finger(node_id, id)
+            .map(|(_, node)| node)
+    }
+
+    /// Get the highest finger strictly between the node and the id, along with its index
+    ///
+    /// When several candidates of the finger are between the node and the id, the one with
+    /// the lowest round trip time is returned.
+    pub(crate) fn closest_preceding_finger(&self, node_id: u64, id: u64) -> Option<(u8, Node)> {
         let state = self.shared_state();
 
         let fingers = state.finger_table.clone();
         drop(state);
 
-        for finger in fingers.iter().rev() {
-            if Node::is_between_on_ring_exclusive(finger.node.id.into(), node_id, id) {
-                return Some(finger.node.clone());
+        for (index, finger) in fingers.iter().enumerate().rev() {
+            let cheapest = finger
+                .candidates
+                .iter()
+                .filter(|candidate| {
+                    Node::is_between_on_ring_exclusive(candidate.node.id.into(), node_id, id)
+                })
+                .min_by_key(|candidate| candidate.cost());
+            if let Some(candidate) = cheapest {
+                return Some((index as u8, candidate.node.clone()));
             }
         }
 
         None
     }
 
+    /// Point the finger to the given node
+    ///
+    /// When the node changes, the candidates of the finger are reset to the node.
     pub(crate) fn update_finger(&self, finger_id: usize, node: Node) {
         let mut state = self.shared_state();
-        state.finger_table[finger_id].node = node;
+        if state.finger_table[finger_id].node != node {
+            let candidate = Self::known_candidate(&state, node.clone());
+            let finger = &mut state.finger_table[finger_id];
+            finger.node = node;
+            finger.candidates = vec![candidate];
+        }
+
+        drop(state);
+    }
+
+    /// Set the nodes which can be used instead of the node of the finger
+    ///
+    /// The measured round trip times of the nodes are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `finger_id` - The index of the finger
+    /// * `nodes` - Nodes from the interval of the finger, at most [`Finger::MAX_CANDIDATES`]
+    ///   including the node of the finger are kept
+    pub(crate) fn set_finger_candidates(&self, finger_id: usize, nodes: Vec<Node>) {
+        let mut state = self.shared_state();
+        let mut candidates = Vec::with_capacity(Finger::MAX_CANDIDATES);
+        let primary = state.finger_table[finger_id].node.clone();
+        for node in std::iter::once(primary).chain(nodes) {
+            if candidates.len() == Finger::MAX_CANDIDATES {
+                break;
+            }
+            if !candidates.iter().any(|c: &Candidate| c.node == node) {
+                candidates.push(Self::known_candidate(&state, node));
+            }
+        }
+        state.finger_table[finger_id].candidates = candidates;
 
         drop(state);
     }
 
+    /// Record a round trip time sample of the given node
+    pub(crate) fn record_rtt(&self, node: &Node, rtt: Duration) {
+        let mut state = self.shared_state();
+        state
+            .finger_table
+            .iter_mut()
+            .flat_map(|finger| finger.candidates.iter_mut())
+            .filter(|candidate| candidate.node == *node)
+            .for_each(|candidate| candidate.record_rtt(rtt));
+
+        drop(state);
+    }
+
+    /// Remove the node from the candidates of the fingers, except of the fingers pointing to it
+    pub(crate) fn remove_candidate(&self, node: &Node) {
+        let mut state = self.shared_state();
+        for finger in state.finger_table.iter_mut() {
+            if finger.node != *node {
+                finger
+                    .candidates
+                    .retain(|candidate| candidate.node != *node);
+            }
+        }
+
+        drop(state);
+    }
+
+    /// Create a candidate, which keeps the round trip time measured for the node
+    fn known_candidate(state: &State, node: Node) -> Candidate {
+        state
+            .finger_table
+            .iter()
+            .flat_map(|finger| finger.candidates.iter())
+            .find(|candidate| candidate.node == node)
+            .cloned()
+            .unwrap_or_else(|| Candidate::new(node))
+    }
+
     pub(crate) fn finger_table(&self) -> Vec<Finger> {
         let state = self.shared_state();
         state.finger_table.clone()
@@ -281,6 +370,55 @@ mod tests {
         assert_eq!(store.db().closest_preceding_node(10, 28), Some(successor));
     }
 
+    #[test]
+    fn closest_preceding_finger_prefers_the_cheapest_candidate() {
+        let addr = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let node = Node::with_id(NodeId(10), addr(42001));
+        let store = NodeStore::new(node.clone(), 3);
+        let near = Node::with_id(NodeId(20), addr(42002));
+        let far = Node::with_id(NodeId(22), addr(42003));
+
+        // Finger 3 covers [18, 26)
+        store.db().update_finger(3, near.clone());
+        store.db().set_finger_candidates(3, vec![far.clone()]);
+        assert_eq!(
+            store.db().finger_table()[3]
+                .candidates
+                .iter()
+                .map(|c| c.node.clone())
+                .collect::<Vec<_>>(),
+            vec![near.clone(), far.clone()]
+        );
+
+        // Unmeasured candidates keep the node of the finger
+        assert_eq!(
+            store.db().closest_preceding_finger(10, 25),
+            Some((3, near.clone()))
+        );
+
+        store.db().record_rtt(&near, Duration::from_millis(40));
+        store.db().record_rtt(&far, Duration::from_millis(5));
+        assert_eq!(
+            store.db().closest_preceding_finger(10, 25),
+            Some((3, far.clone()))
+        );
+        // Only the candidates which make progress are considered
+        assert_eq!(
+            store.db().closest_preceding_finger(10, 21),
+            Some((3, near.clone()))
+        );
+
+        store.db().remove_candidate(&far);
+        assert_eq!(
+            store.db().closest_preceding_finger(10, 25),
+            Some((3, near.clone()))
+        );
+
+        // The node of a finger is never removed from its candidates
+        store.db().remove_candidate(&near);
+        assert_eq!(store.db().closest_preceding_finger(10, 25), Some((3, near)));
+    }
+
     #[test]
     fn test_successor_list_init() {
         let node = Node::with_id(NodeId(10), SocketAddr::from(([127, 0, 0, 1], 42001)));
@@ -1,34 +1,177 @@
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
-use crate::{Client, Node, NodeService};
+use error_stack::Report;
+use thiserror::Error;
 
+use crate::{error::ServiceError, Client, Node, NodeService};
+
+/// A node of the ring to join through, given by an address or a host name
+///
+/// A host name may resolve to several addresses, e.g. one per node of the ring,
+/// each of them is tried in turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seed {
+    host: String,
+    port: u16,
+}
+
+impl Seed {
+    /// The port used when the seed doesn't have one
+    pub const DEFAULT_PORT: u16 = 42000;
+
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+        }
+    }
+
+    /// Resolve the seed to the addresses of its nodes
+    pub async fn resolve(&self) -> std::io::Result<Vec<SocketAddr>> {
+        let addrs = tokio::net::lookup_host((self.host.as_str(), self.port)).await?;
+        Ok(addrs.collect())
+    }
+}
+
+impl From<SocketAddr> for Seed {
+    fn from(addr: SocketAddr) -> Self {
+        Self::new(addr.ip().to_string(), addr.port())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid seed '{0}', expected HOST[:PORT]")]
+pub struct InvalidSeed(String);
+
+impl FromStr for Seed {
+    type Err = InvalidSeed;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = value.parse::<SocketAddr>() {
+            return Ok(addr.into());
+        }
+        if let Ok(ip) = value.trim_matches(['[', ']']).parse::<IpAddr>() {
+            return Ok(Self::new(ip.to_string(), Self::DEFAULT_PORT));
+        }
+
+        let (host, port) = match value.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().map_err(|_| InvalidSeed(value.to_string()))?;
+                (host, port)
+            }
+            None => (value, Self::DEFAULT_PORT),
+        };
+        if host.is_empty() || host.contains([':', '[', ']', '/']) {
+            return Err(InvalidSeed(value.to_string()));
+        }
+
+        Ok(Self::new(host, port))
+    }
+}
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Join the ring through the first of the seeds which responds
+///
+/// Each seed is resolved to all of its addresses, which are tried in turn. When none of them
+/// responds, the node keeps retrying in the background with a growing delay. Meanwhile it
+/// serves as a ring of its own.
+///
+/// # Arguments
+///
+/// * `node_service` - The node joining the ring
+/// * `seeds` - The nodes of the ring to join through, the node starts a new ring when it's empty
+///
+/// # Errors
+///
+/// Fails with [`ServiceError::DuplicateId`] when the ring has a node with the same id, the node
+/// must not be started then. When the duplicate is found by a retry in the background, the
+/// error is logged and the node stops retrying.
 pub async fn join_ring<T: Client + Clone + Sync + Send + 'static>(
     node_service: Arc<NodeService<T>>,
-    ring: SocketAddr,
-    max_retries: u32,
-) {
-    // TODO: make this configurable
-    const WAIT_BETWEEN_RETRIES: Duration = Duration::from_secs(3);
-    let mut attempt = 0;
-    loop {
-        attempt += 1;
-        log::info!("{} attempt to join ring: {:?}", attempt, ring);
-
-        let node = Node::new(ring);
-        tokio::time::sleep(Duration::from_secs(1)).await;
-
-        if let Ok(_) = node_service.join(node).await {
-            log::info!("Joined ring: {:?}", ring);
-            break;
-        } else {
-            if attempt >= max_retries {
-                log::error!("Failed to join ring: {:?}", ring);
-                panic!("Failed to join ring: {:?}", ring)
+    seeds: Vec<Seed>,
+) -> Result<(), Report<ServiceError>> {
+    const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+    const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+    log::info!("Node {} has id {}", node_service.addr(), node_service.id());
+    if seeds.is_empty() || try_join(&node_service, &seeds).await? {
+        return Ok(());
+    }
+
+    tokio::spawn(async move {
+        let mut delay = INITIAL_RETRY_DELAY;
+        loop {
+            log::warn!("Failed to join ring, retrying in {:?}", delay);
+            tokio::time::sleep(delay).await;
+            match try_join(&node_service, &seeds).await {
+                Ok(false) => {}
+                Ok(true) => break,
+                Err(report) => {
+                    log::error!("Giving up joining the ring: {:?}", report);
+                    break;
+                }
             }
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
         }
+    });
+
+    Ok(())
+}
+
+/// Try to join the ring through each address of the seeds, return whether the node joined
+///
+/// Fails when the node can't join the ring at all, i.e. its id is taken.
+async fn try_join<T: Client + Clone + Sync + Send + 'static>(
+    node_service: &NodeService<T>,
+    seeds: &[Seed],
+) -> Result<bool, Report<ServiceError>> {
+    for seed in seeds {
+        let addrs = match seed.resolve().await {
+            Ok(addrs) => addrs,
+            Err(err) => {
+                log::warn!("Failed to resolve seed {}: {}", seed, err);
+                continue;
+            }
+        };
 
-        tokio::time::sleep(WAIT_BETWEEN_RETRIES).await;
+        for addr in addrs {
+            if addr == node_service.addr() {
+                continue;
+            }
+
+            log::info!("Attempt to join ring through {} ({})", addr, seed);
+            match node_service.join(Node::new(addr)).await {
+                // A seed which resolves to this node makes it its own successor
+                Ok(_) if node_service.store().successor().id == node_service.id() => {
+                    log::debug!("Seed {} is this node", addr);
+                }
+                Ok(_) => {
+                    log::info!("Joined ring through {}", addr);
+                    return Ok(true);
+                }
+                Err(report) if matches!(report.current_context(), ServiceError::DuplicateId) => {
+                    return Err(report);
+                }
+                Err(report) => log::warn!("Failed to join ring through {}: {:?}", addr, report),
+            }
+        }
     }
+
+    Ok(false)
 }
 
 pub fn background_tasks<T: Client + Clone + Sync + Send + 'static>(
@@ -50,6 +193,103 @@ pub fn background_tasks<T: Client + Clone + Sync + Send + 'static>(
             service.reconcile_successors().await;
 
             service.fix_fingers().await;
+
+            service.measure_fingers().await;
+
+            service.evict_idle_clients();
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientError, MockClient};
+    use crate::service::tests::{get_lock, MTX};
+    use crate::Address;
+
+    #[test]
+    fn test_parse_seed() {
+        let seed = |value: &str| value.parse::<Seed>().unwrap().to_string();
+
+        assert_eq!(seed("127.0.0.1:42001"), "127.0.0.1:42001");
+        assert_eq!(seed("127.0.0.1"), "127.0.0.1:42000");
+        assert_eq!(seed("[::1]:42001"), "[::1]:42001");
+        assert_eq!(seed("[::1]"), "[::1]:42000");
+        assert_eq!(seed("leader:42001"), "leader:42001");
+        assert_eq!(seed("leader"), "leader:42000");
+        assert!("leader:port".parse::<Seed>().is_err());
+        assert!("".parse::<Seed>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_seed() {
+        let addrs = Seed::new("localhost", 42001).resolve().await.unwrap();
+
+        assert!(!addrs.is_empty());
+        assert!(addrs.iter().all(|addr| addr.port() == 42001));
+    }
+
+    #[tokio::test]
+    async fn test_join_ring_tries_the_seeds_in_turn() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+
+        ctx.expect().returning(|addr: Address, _| {
+            let mut client = MockClient::new();
+            if addr.port() == 42016 {
+                client
+                    .expect_find_successor()
+                    .times(1)
+                    .returning(|_| Err(Report::new(ClientError::ConnectionFailed("down".into()))));
+            }
+            if addr.port() == 42032 {
+                client.expect_find_successor().times(1).returning(|_| {
+                    Ok(Node::with_id(64, SocketAddr::from(([127, 0, 0, 1], 42064))))
+                });
+            }
+            Ok(client)
+        });
+
+        let service: Arc<NodeService<MockClient>> = Arc::new(NodeService::new(
+            SocketAddr::from(([127, 0, 0, 1], 42008)),
+            3,
+        ));
+        let seeds = vec!["127.0.0.1:42008", "127.0.0.1:42016", "127.0.0.1:42032"]
+            .into_iter()
+            .map(|seed| seed.parse().unwrap())
+            .collect();
+
+        join_ring(service.clone(), seeds).await.unwrap();
+
+        assert_eq!(service.get_successor().await.unwrap().id, 64.into());
+    }
+
+    #[tokio::test]
+    async fn test_join_ring_fails_when_the_id_is_taken() {
+        let _m = get_lock(&MTX);
+        let ctx = MockClient::init_context();
+
+        ctx.expect().returning(|addr: Address, _| {
+            let mut client = MockClient::new();
+            if addr.port() == 42016 {
+                client.expect_find_successor().times(1).returning(|id| {
+                    Ok(Node::with_id(id, SocketAddr::from(([127, 0, 0, 1], 42032))))
+                });
+            }
+            if addr.port() == 42032 {
+                client.expect_ping().times(1).returning(|| Ok(()));
+            }
+            Ok(client)
+        });
+
+        let service: Arc<NodeService<MockClient>> = Arc::new(NodeService::new(
+            SocketAddr::from(([127, 0, 0, 1], 42008)),
+            3,
+        ));
+        let seeds = vec!["127.0.0.1:42016".parse().unwrap()];
+
+        let report = join_ring(service, seeds).await.unwrap_err();
+        assert_eq!(report.current_context(), &ServiceError::DuplicateId);
+    }
+}
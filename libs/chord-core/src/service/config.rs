@@ -0,0 +1,113 @@
+use std::net::SocketAddr;
+
+use crate::client::ClientConfig;
+use crate::discovery::DiscoveryConfig;
+use crate::lookup::CacheConfig;
+use crate::NodeId;
+
+/// Configuration of a node service
+#[derive(Debug, Clone, Default)]
+pub struct ServiceConfig {
+    /// The config of the clients used to talk to other nodes,
+    /// its cluster secret is also used to verify the incoming notify calls
+    pub client: ClientConfig,
+    /// How the finger table is kept up to date
+    pub fix_fingers: FixFingers,
+    /// Limits of the cache of the lookup results
+    pub cache: CacheConfig,
+    /// Multicast discovery of the other nodes, disabled when it's not set
+    pub discovery: Option<DiscoveryConfig>,
+    /// The address other nodes reach the node on, when it differs from the address the node
+    /// listens on, e.g. behind NAT or when listening on an unspecified address
+    pub advertise: Option<SocketAddr>,
+    /// The host name other nodes reach the node by, with the port of the advertised address
+    pub host: Option<String>,
+    /// The id of the node, derived from the host name or the advertised address when it's not set
+    pub id: Option<NodeId>,
+}
+
+impl ServiceConfig {
+    /// Set the strategy used to fix the fingers
+    ///
+    /// # Arguments
+    ///
+    /// * `fix_fingers` - The strategy
+    pub fn fix_fingers(mut self, fix_fingers: FixFingers) -> Self {
+        self.fix_fingers = fix_fingers;
+        self
+    }
+
+    /// Set the limits of the cache of the lookup results
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The limits, a zero capacity disables the cache
+    pub fn cache(mut self, cache: CacheConfig) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Set the multicast discovery of the other nodes
+    ///
+    /// # Arguments
+    ///
+    /// * `discovery` - The configuration of the discovery, `None` disables it
+    pub fn discovery(mut self, discovery: Option<DiscoveryConfig>) -> Self {
+        self.discovery = discovery;
+        self
+    }
+
+    /// Set the address other nodes reach the node on
+    ///
+    /// # Arguments
+    ///
+    /// * `advertise` - The address, `None` advertises the address the node listens on
+    pub fn advertise(mut self, advertise: Option<SocketAddr>) -> Self {
+        self.advertise = advertise;
+        self
+    }
+
+    /// Set the host name other nodes reach the node by
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The host name, `None` to be reached by the advertised address
+    pub fn host(mut self, host: Option<String>) -> Self {
+        self.host = host;
+        self
+    }
+
+    /// Set the id of the node
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id, `None` derives it from the host name or the advertised address
+    pub fn id(mut self, id: Option<NodeId>) -> Self {
+        self.id = id;
+        self
+    }
+}
+
+impl From<ClientConfig> for ServiceConfig {
+    fn from(client: ClientConfig) -> Self {
+        Self {
+            client,
+            ..Self::default()
+        }
+    }
+}
+
+/// Strategy used by [`crate::NodeService::fix_fingers`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FixFingers {
+    /// Look up every finger on each call
+    All,
+    /// Look up every finger on each call, except the fingers whose start is covered by the
+    /// node of the previous finger. Those point to the same node without a lookup.
+    #[default]
+    SkipCovered,
+    /// Look up one finger per call, going through the fingers in order
+    Incremental,
+    /// Look up one randomly chosen finger per call
+    Random,
+}
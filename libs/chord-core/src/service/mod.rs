@@ -1,22 +1,42 @@
 use async_recursion::async_recursion;
 use error_stack::{Report, Result, ResultExt};
+use futures::future::join_all;
+use rand::Rng;
 
+use crate::auth::ClusterSecret;
 use crate::client::{ClientError, ClientsPool};
+use crate::lookup::{Hop, Lookup, LookupCache, NextHop, PrecedingFinger};
 use crate::node::store::{Db, NodeStore};
 use crate::node::Finger;
-use crate::{Client, Node, NodeId};
+use crate::{deadline, Client, Node, NodeId};
+use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::time::Duration;
 use std::vec;
+use tokio::time::Instant;
 
+pub(crate) mod config;
 #[cfg(test)]
 pub(crate) mod tests;
 
+pub use config::{FixFingers, ServiceConfig};
+
 #[derive(Debug)]
 pub struct NodeService<C: Client> {
     id: NodeId,
     addr: SocketAddr,
+    /// The host name other nodes reach the node by
+    host: Option<String>,
     store: NodeStore,
+    secret: Option<ClusterSecret>,
+    fix_fingers: FixFingers,
+    /// The finger fixed next by [`FixFingers::Incremental`]
+    next_finger: AtomicU8,
+    /// The finger candidate measured next by [`NodeService::measure_fingers`]
+    next_candidate: AtomicUsize,
+    /// The owners of the ranges of ids looked up recently
+    cache: LookupCache,
 
     clients: ClientsPool<C>,
 }
@@ -29,17 +49,54 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
     /// * `socket_addr` - The address of the node
     /// * `replication_factor` - The number of successors to keep track of
     pub fn new(socket_addr: SocketAddr, replication_factor: usize) -> Self {
-        let id: NodeId = socket_addr.into();
-        Self::with_id(id, socket_addr, replication_factor)
+        Self::with_config(socket_addr, replication_factor, ServiceConfig::default())
+    }
+
+    /// Create a new node service with the given config
+    ///
+    /// The id of the node is `config.id`, or it's derived from the advertised address when it's
+    /// not set. The advertised address is the address the node announces to other nodes, or
+    /// its host name and port when `config.host` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `socket_addr` - The address the node listens on, advertised unless `config.advertise` is set
+    /// * `replication_factor` - The number of successors to keep track of
+    /// * `config` - The config of the service, or just the config of the clients used to
+    ///   talk to other nodes
+    pub fn with_config(
+        socket_addr: SocketAddr,
+        replication_factor: usize,
+        config: impl Into<ServiceConfig>,
+    ) -> Self {
+        let config = config.into();
+        let addr = config.advertise.unwrap_or(socket_addr);
+        let node = Node::new(addr).with_host(config.host);
+        let id = config.id.unwrap_or_else(|| NodeId::from(&node.address()));
+        let mut service = Self::with_node(Node { id, ..node }, replication_factor);
+        service.secret = config.client.secret.clone();
+        service.fix_fingers = config.fix_fingers;
+        service.cache = LookupCache::new(config.cache);
+        service.clients = ClientsPool::new(config.client);
+        service
     }
 
+    #[cfg(test)]
     fn with_id(id: impl Into<NodeId>, addr: SocketAddr, replication_factor: usize) -> Self {
-        let id = id.into();
-        let store = NodeStore::new(Node::with_id(id, addr), replication_factor);
+        Self::with_node(Node::with_id(id, addr), replication_factor)
+    }
+
+    fn with_node(node: Node, replication_factor: usize) -> Self {
         Self {
-            id,
-            addr,
-            store,
+            id: node.id,
+            addr: node.addr,
+            host: node.host.clone(),
+            store: NodeStore::new(node, replication_factor),
+            secret: None,
+            fix_fingers: FixFingers::default(),
+            next_finger: AtomicU8::new(0),
+            next_candidate: AtomicUsize::new(0),
+            cache: LookupCache::default(),
             clients: ClientsPool::default(),
         }
     }
@@ -48,6 +105,15 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
         self.id
     }
 
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Get the node as it's announced to other nodes
+    pub fn node(&self) -> Node {
+        Node::with_id(self.id, self.addr).with_host(self.host.clone())
+    }
+
     pub(crate) fn store(&self) -> Db {
         self.store.db()
     }
@@ -55,17 +121,44 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
     /// Find the successor of the given id.
     ///
     /// If the given id is in the range of the current node and its successor, the successor is returned.
-    /// Otherwise, the successor of the closest preceding node is returned.
+    /// Otherwise, the cached owner of the id is returned, or the successor of the closest
+    /// preceding node when the id isn't cached.
+    ///
+    /// It's meant for the lookups this node starts, requests of other nodes are served with
+    /// [`NodeService::find_successor_uncached`].
     ///
     /// # Arguments
     ///
     /// * `id` - The id to find the successor for
     pub async fn find_successor(&self, id: NodeId) -> Result<Node, error::ServiceError> {
         if let Some(successor) = self.find_immediate_successor(id).await? {
-            Ok(successor)
-        } else {
-            self.find_successor_using_finger_table(id, None).await
+            return Ok(successor);
+        }
+        if let Some(owner) = self.cache.get(id) {
+            return Ok(owner);
+        }
+
+        self.find_successor_uncached(id).await
+    }
+
+    /// Find the successor of the given id without reading the cache, the result is cached.
+    ///
+    /// It's used to refresh the routing state, which would otherwise be only as fresh as the cache,
+    /// and to serve the requests of other nodes. A node which joined within a cached range is only
+    /// known to its neighbours, so this node would keep answering with the old owner until the
+    /// range expires, and a joining node would be told the wrong successor.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id to find the successor for
+    pub async fn find_successor_uncached(&self, id: NodeId) -> Result<Node, error::ServiceError> {
+        if let Some(successor) = self.find_immediate_successor(id).await? {
+            return Ok(successor);
         }
+
+        let owner = self.find_successor_using_finger_table(id, None).await?;
+        self.cache.insert(id, owner.clone());
+        Ok(owner)
     }
 
     /// Find the successor of the given id using the successor list.
@@ -105,14 +198,23 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
         if n.id == self.id {
             let error = format!("Cannot find successor of id '{}' using finger table", id);
             log::error!("{}", error);
-            return Err(Report::new(error::ServiceError::Unexpected));
+            return Err(Report::new(error::ServiceError::NotOwner)).attach_printable(error);
         }
 
-        let client: Arc<C> = self.client(&n).await;
-        match client.find_successor(id).await {
+        let result = self
+            .clients
+            .call(&n, |client| async move { client.find_successor(id).await })
+            .await;
+        match result {
             Ok(successor) => Result::Ok(successor),
             Err(report) => match (*report.current_context()).clone() {
                 ClientError::ConnectionFailed(_) => {
+                    self.client_failed(&n, report.current_context());
+                    self.find_successor_using_finger_table(id, Some(n.id)).await
+                }
+                // A hung node is routed around as long as the deadline allows it
+                ClientError::Timeout(_) if !deadline::budget(Duration::MAX).is_zero() => {
+                    self.client_failed(&n, report.current_context());
                     self.find_successor_using_finger_table(id, Some(n.id)).await
                 }
                 err => Result::Err(report.change_context(err.into())),
@@ -120,6 +222,53 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
         }
     }
 
+    /// Look up the successor of the given id iteratively
+    ///
+    /// Unlike [`NodeService::find_successor`], the node contacts every hop of the lookup itself,
+    /// see [`crate::lookup`]. The result contains the path of the lookup, starting with this node.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id to find the successor for
+    pub async fn lookup(&self, id: NodeId) -> Result<Lookup, error::ServiceError> {
+        let hop = Hop {
+            node: self.node(),
+            finger: None,
+            latency: Duration::ZERO,
+        };
+
+        let lookup = Lookup::resume(&self.clients, id, hop, self.next_hop(id))
+            .await
+            .map_err(|report| {
+                let context = report.current_context().clone();
+                report.change_context(context.into())
+            })?;
+        // The owner is the successor of the last hop
+        let last_hop = &lookup.path.last().unwrap().node;
+        self.cache
+            .insert(NodeId(last_hop.id.0.wrapping_add(1)), lookup.owner.clone());
+
+        Ok(lookup)
+    }
+
+    /// Get the routing information for a step of an iterative lookup of the given id
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id being looked up
+    pub fn next_hop(&self, id: NodeId) -> NextHop {
+        let finger = self
+            .store()
+            .closest_preceding_finger(self.id.0, id.0)
+            .map(|(index, node)| PrecedingFinger { index, node });
+
+        NextHop {
+            node: self.node(),
+            successor: self.store().successor(),
+            finger,
+        }
+    }
+
     pub async fn get_predecessor(&self) -> Result<Option<Node>, error::ServiceError> {
         Ok(self.store().predecessor())
     }
@@ -137,35 +286,107 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
     /// This method is used to join the chord ring. It will find the successor of its own id
     /// and set it as the successor.
     ///
+    /// When another node of the ring has the same id, the join is rejected with
+    /// [`error::ServiceError::DuplicateId`]. A node with the same id which doesn't respond is
+    /// most likely this node before it moved to another address, the join fails until the ring
+    /// drops it.
+    ///
     /// # Arguments
     ///
     /// * `node` - The node to join the ring with. It's an existing node in the ring.
     pub async fn join(&self, node: Node) -> Result<(), error::ServiceError> {
-        let client: Arc<C> = self.client(&node).await;
-        let successor = client
-            .find_successor(self.id)
+        let id = self.id;
+        let successor = self
+            .clients
+            .call(
+                &node,
+                |client| async move { client.find_successor(id).await },
+            )
             .await
             .change_context(error::ServiceError::Unexpected)?;
+
+        if successor.id == self.id && successor.address() != self.node().address() {
+            let message = format!("Node id '{}' is used by {}", self.id, successor.address());
+            let ping = self
+                .clients
+                .call(&successor, |client| async move { client.ping().await })
+                .await;
+            return match ping {
+                Ok(_) => {
+                    Err(Report::new(error::ServiceError::DuplicateId)).attach_printable(message)
+                }
+                Err(report) => Err(report)
+                    .change_context(error::ServiceError::Unavailable)
+                    .attach_printable(message),
+            };
+        }
         self.store().set_successor(successor);
 
         Ok(())
     }
 
+    /// Check if the ring of the given node routes the id of this node to this node,
+    /// i.e. both nodes are members of the same ring
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node to ask for the successor of the id of this node
+    pub(crate) async fn shares_ring_with(&self, node: &Node) -> Result<bool, error::ServiceError> {
+        let id = self.id;
+        let owner = self
+            .clients
+            .call(
+                node,
+                |client| async move { client.find_successor(id).await },
+            )
+            .await
+            .change_context(error::ServiceError::Unexpected)?;
+
+        Ok(owner.id == self.id)
+    }
+
     /// Notify the node about a potential new predecessor.
     ///
     /// If the predecessor is not set or the given node is in the range of the current node and the
     /// predecessor, the predecessor is set to the given node.
     ///
+    /// When the node has a cluster secret, the call is rejected with
+    /// [`error::ServiceError::Unauthenticated`] unless the token is signed with the same secret.
+    /// This covers joins as well: a join has no call of its own, the joining node only becomes a
+    /// member of the ring when it notifies its successor.
+    /// A node which fails [`Node::validate`] is rejected with
+    /// [`error::ServiceError::InvalidArgument`].
+    ///
     /// # Arguments
     ///
     /// * `node` - The node which might be the new predecessor
-    pub fn notify(&self, node: Node) {
+    /// * `token` - The token sent with the call, see [`ClusterSecret::sign`]
+    pub fn notify(&self, node: Node, token: Option<&[u8]>) -> Result<(), error::ServiceError> {
+        node.validate().map_err(|err| {
+            Report::new(error::ServiceError::InvalidArgument).attach_printable(err)
+        })?;
+
+        if let Some(secret) = &self.secret {
+            if !token.is_some_and(|token| secret.verify(&node, token)) {
+                log::warn!("Rejected unauthenticated notify from {:?}", node.addr);
+                return Err(Report::new(error::ServiceError::Unauthenticated))
+                    .attach_printable(format!("Invalid token for {:?}", node.addr));
+            }
+        }
+
         let predecessor = self.store().predecessor();
         if predecessor.is_none()
-            || Node::is_between_on_ring(node.id.0, predecessor.unwrap().id.0, self.id.0)
+            || Node::is_between_on_ring(node.id.0, predecessor.as_ref().unwrap().id.0, self.id.0)
         {
+            // The new predecessor took over the ids up to itself from this node
+            let from = predecessor.map_or(node.id, |predecessor| {
+                NodeId(predecessor.id.0.wrapping_add(1))
+            });
+            self.cache.insert(from, node.clone());
             self.store().set_predecessor(node);
         }
+
+        Ok(())
     }
 
     /// Stabilize the node
@@ -181,25 +402,35 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
     /// > This method should be called periodically.
     pub async fn stabilize(&self) -> Result<(), error::ServiceError> {
         let successor = self.store().successor();
-        let client: Arc<C> = self.client(&successor).await;
-        let result = client.predecessor().await;
-        drop(client);
+        let result = self
+            .clients
+            .call(
+                &successor,
+                |client| async move { client.predecessor().await },
+            )
+            .await;
+        if let Err(report) = &result {
+            self.client_failed(&successor, report.current_context());
+        }
 
         if let Ok(Some(x)) = result {
             if Node::is_between_on_ring(x.id.0, self.id.0, self.store().successor().id.0) {
+                // The new successor took over the ids up to itself from the old one
+                self.cache
+                    .insert(NodeId(self.id.0.wrapping_add(1)), x.clone());
                 self.store().set_successor(x);
             }
         }
 
         let successor = self.store().successor();
-        let client: Arc<C> = self.client(&successor).await;
-
-        client
-            .notify(Node {
-                id: self.id,
-                addr: self.addr,
-            })
+        let node = self.node();
+        self.clients
+            .call(
+                &successor,
+                |client| async move { client.notify(node).await },
+            )
             .await
+            .inspect_err(|report| self.client_failed(&successor, report.current_context()))
             .change_context(error::ServiceError::Unexpected)?;
 
         Ok(())
@@ -207,9 +438,15 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
 
     pub async fn reconcile_successors(&self) {
         let successor = self.store().successor();
-        let client: Arc<C> = self.client(&successor).await;
-
-        match client.successor_list().await {
+        let result = self
+            .clients
+            .call(
+                &successor,
+                |client| async move { client.successor_list().await },
+            )
+            .await;
+
+        match result {
             Ok(successors) => {
                 let mut new_successors = vec![successor];
                 new_successors.extend(successors);
@@ -222,6 +459,8 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
                     successor.addr
                 );
                 log::debug!("Successor {:?} error: {err:?}", successor.addr);
+                self.clients.remove(&successor);
+                self.cache.invalidate(&successor);
 
                 let successors = self.store().successor_list();
                 self.store().set_successor_list(successors[1..].to_vec());
@@ -239,8 +478,11 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
     /// > This method should be called periodically.
     pub async fn check_predecessor(&self) -> Result<(), error::ServiceError> {
         if let Some(predecessor) = self.store().predecessor() {
-            let client: Arc<C> = self.client(&predecessor).await;
-            match client.ping().await {
+            let ping = self
+                .clients
+                .call(&predecessor, |client| async move { client.ping().await })
+                .await;
+            match ping {
                 Ok(_) => Ok(()),
                 Err(err) => {
                     log::info!(
@@ -249,6 +491,8 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
                         err
                     );
                     self.store().unset_predecessor();
+                    self.clients.remove(&predecessor);
+                    self.cache.invalidate(&predecessor);
                     Ok(())
                 }
             }
@@ -259,20 +503,150 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
 
     /// Fix fingers
     ///
-    /// This method is used to fix the fingers. It iterates over all fingers and re-requests the
-    /// successor of the finger's id. Then sets the successor of the finger to the retrieved node.
+    /// This method is used to fix the fingers. It iterates over the fingers selected by the
+    /// [`FixFingers`] strategy and re-requests the successor of the finger's id. Then sets the
+    /// successor of the finger to the retrieved node.
+    ///
+    /// The successors of the finger's node which fall into the interval of the finger are kept
+    /// as its candidates, see [`NodeService::measure_fingers`]. The successor lists of the
+    /// fingers' nodes are requested concurrently, once per node.
     ///
     /// > **Note**
     /// >
     /// > This method should be called periodically.
     pub async fn fix_fingers(&self) {
-        for i in 0..Finger::FINGER_TABLE_SIZE {
-            let finger_id = Finger::finger_id(self.id.0, (i + 1) as u8);
-            let result = { self.find_successor(NodeId(finger_id)).await };
-            if let Ok(successor) = result {
-                self.store().update_finger(i.into(), successor)
-            } else {
-                log::error!("Failed to fix finger: {:?}", result.unwrap_err());
+        let fingers: Vec<u8> = match self.fix_fingers {
+            FixFingers::All | FixFingers::SkipCovered => (0..Finger::FINGER_TABLE_SIZE).collect(),
+            FixFingers::Incremental => {
+                let next = self.next_finger.fetch_add(1, Ordering::Relaxed);
+                vec![next % Finger::FINGER_TABLE_SIZE]
+            }
+            FixFingers::Random => vec![rand::thread_rng().gen_range(0..Finger::FINGER_TABLE_SIZE)],
+        };
+
+        let mut fixed: Vec<(u8, Node)> = Vec::new();
+        let mut previous: Option<Node> = None;
+        for i in fingers {
+            let finger_id = Finger::finger_id(self.id.0, i + 1);
+            // The previous finger's node is the successor of every id up to it
+            let covered = previous.filter(|node| {
+                self.fix_fingers == FixFingers::SkipCovered
+                    && Node::is_between_on_ring(finger_id, self.id.0, node.id.0)
+            });
+            let result = match covered {
+                Some(node) => Ok(node),
+                None => self.find_successor_uncached(NodeId(finger_id)).await,
+            };
+            previous = result.as_ref().ok().cloned();
+            match result {
+                Ok(successor) => {
+                    self.store().update_finger(i.into(), successor.clone());
+                    fixed.push((i, successor));
+                }
+                Err(report) => log::error!("Failed to fix finger: {:?}", report),
+            }
+        }
+
+        let mut nodes: Vec<&Node> = Vec::new();
+        for (_, node) in &fixed {
+            if !nodes.iter().any(|known| known.id == node.id) {
+                nodes.push(node);
+            }
+        }
+        let successor_lists: HashMap<NodeId, Vec<Node>> = nodes
+            .iter()
+            .map(|node| node.id)
+            .zip(join_all(nodes.iter().map(|node| self.finger_successors(node))).await)
+            .collect();
+
+        for (i, node) in &fixed {
+            let candidates = successor_lists[&node.id]
+                .iter()
+                .filter(|successor| Finger::in_interval(self.id.0, (*i).into(), successor.id.0))
+                .cloned()
+                .collect();
+            self.store().set_finger_candidates((*i).into(), candidates);
+        }
+    }
+
+    /// Get the successors of a finger's node, which are candidates for the same finger
+    async fn finger_successors(&self, node: &Node) -> Vec<Node> {
+        if node.id == self.id {
+            return Vec::new();
+        }
+
+        let result = self
+            .clients
+            .call(node, |client| async move { client.successor_list().await })
+            .await;
+        match result {
+            Ok(successors) => successors
+                .into_iter()
+                .filter(|successor| successor.id != self.id)
+                .collect(),
+            Err(report) => {
+                log::debug!("Failed to get successors of {:?}: {:?}", node.addr, report);
+                self.client_failed(node, report.current_context());
+                Vec::new()
+            }
+        }
+    }
+
+    /// The maximum number of finger candidates pinged by [`NodeService::measure_fingers`]
+    pub const MEASURED_CANDIDATES: usize = 8;
+
+    /// The time [`NodeService::measure_fingers`] waits for the candidates to respond
+    pub const MEASURE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Measure the round trip times to the finger candidates
+    ///
+    /// Lookups route through the candidate with the lowest round trip time. Candidates which
+    /// don't respond are removed, unless a finger points to them.
+    ///
+    /// At most [`NodeService::MEASURED_CANDIDATES`] candidates are pinged per call, concurrently
+    /// and within [`NodeService::MEASURE_TIMEOUT`]. The following calls continue with the next
+    /// candidates, so all of them are measured over a few calls.
+    ///
+    /// > **Note**
+    /// >
+    /// > This method should be called periodically.
+    pub async fn measure_fingers(&self) {
+        let mut nodes: Vec<Node> = Vec::new();
+        for finger in self.store().finger_table() {
+            for candidate in finger.candidates {
+                if candidate.node.id != self.id && !nodes.contains(&candidate.node) {
+                    nodes.push(candidate.node);
+                }
+            }
+        }
+        if nodes.is_empty() {
+            return;
+        }
+
+        let count = Self::MEASURED_CANDIDATES.min(nodes.len());
+        let start = self.next_candidate.fetch_add(count, Ordering::Relaxed) % nodes.len();
+        let batch = nodes.iter().cycle().skip(start).take(count);
+        let deadline = Instant::now() + Self::MEASURE_TIMEOUT;
+        deadline::scope(
+            Some(deadline),
+            join_all(batch.map(|node| self.measure(node))),
+        )
+        .await;
+    }
+
+    /// Ping a finger candidate, removing it when it doesn't respond
+    async fn measure(&self, node: &Node) {
+        let started = Instant::now();
+        let ping = self
+            .clients
+            .call(node, |client| async move { client.ping().await })
+            .await;
+        match ping {
+            Ok(()) => self.store().record_rtt(node, started.elapsed()),
+            Err(report) => {
+                log::debug!("Finger candidate {:?} is down: {:?}", node.addr, report);
+                self.client_failed(node, report.current_context());
+                self.store().remove_candidate(node);
             }
         }
     }
@@ -299,11 +673,23 @@ impl<C: Client + Clone + Sync + Send + 'static> NodeService<C> {
     fn closest_preceding_node(&self, id: NodeId) -> Node {
         self.store()
             .closest_preceding_node(self.id.0, id.0)
-            .unwrap_or(Node::with_id(self.id, self.addr))
+            .unwrap_or_else(|| self.node())
+    }
+
+    /// Evict the clients of the nodes which weren't contacted for a while
+    ///
+    /// > **Note**
+    /// >
+    /// > This method should be called periodically.
+    pub fn evict_idle_clients(&self) {
+        self.clients.evict_idle();
     }
 
-    async fn client(&self, node: &Node) -> Arc<C> {
-        self.clients.get_or_init(node).await
+    /// Let the pool back off from a node which can't be reached,
+    /// and stop using the node as the cached owner of its ranges
+    fn client_failed(&self, node: &Node, error: &ClientError) {
+        self.clients.report_error(node, error);
+        self.cache.invalidate(node);
     }
 }
 
@@ -312,20 +698,99 @@ pub mod error {
 
     use crate::client;
 
-    #[derive(Debug, Error)]
+    /// Kind of the error of a call to the node
+    ///
+    /// The kind is sent to the caller as a structured code by the transports, the client
+    /// maps it back with [`client::ClientError::from_service`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
     pub enum ServiceError {
         #[error("Unexpected error")]
         Unexpected,
-        #[error("Client disconnected")]
-        ClientDisconnected,
+        /// A node the call depends on can't be reached
+        #[error("Unavailable")]
+        Unavailable,
+        #[error("Unauthenticated")]
+        Unauthenticated,
+        #[error("Deadline exceeded")]
+        Timeout,
+        /// Another node of the ring has the same id
+        #[error("Duplicate node id")]
+        DuplicateId,
+        /// The node doesn't own the id and doesn't know a node closer to it
+        #[error("Not the owner")]
+        NotOwner,
+        /// The node is at capacity, the call can be retried after a backoff
+        #[error("Overloaded")]
+        Overloaded,
+        /// The request is malformed
+        #[error("Invalid argument")]
+        InvalidArgument,
+    }
+
+    impl ServiceError {
+        const ALL: [Self; 8] = [
+            Self::Unexpected,
+            Self::Unavailable,
+            Self::Unauthenticated,
+            Self::Timeout,
+            Self::DuplicateId,
+            Self::NotOwner,
+            Self::Overloaded,
+            Self::InvalidArgument,
+        ];
+
+        /// Stable code of the error, used by the transports without a code of their own
+        pub fn code(&self) -> &'static str {
+            match self {
+                Self::Unexpected => "unexpected",
+                Self::Unavailable => "unavailable",
+                Self::Unauthenticated => "unauthenticated",
+                Self::Timeout => "timeout",
+                Self::DuplicateId => "duplicate_id",
+                Self::NotOwner => "not_owner",
+                Self::Overloaded => "overloaded",
+                Self::InvalidArgument => "invalid_argument",
+            }
+        }
+
+        /// Get the error with the given [`ServiceError::code`]
+        pub fn from_code(code: &str) -> Option<Self> {
+            Self::ALL.into_iter().find(|err| err.code() == code)
+        }
     }
 
     impl From<client::ClientError> for ServiceError {
         fn from(err: client::ClientError) -> Self {
             match err {
-                client::ClientError::ConnectionFailed(_) => Self::ClientDisconnected,
+                client::ClientError::ConnectionFailed(_) => Self::Unavailable,
+                client::ClientError::Unauthenticated(_) => Self::Unauthenticated,
+                client::ClientError::Timeout(_) => Self::Timeout,
+                client::ClientError::Overloaded(_) => Self::Overloaded,
+                client::ClientError::NotOwner(_) => Self::NotOwner,
+                client::ClientError::DuplicateId(_) => Self::DuplicateId,
                 _ => Self::Unexpected,
             }
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_errors_round_trip_through_the_client() {
+            for err in ServiceError::ALL {
+                assert_eq!(Some(err), ServiceError::from_code(err.code()));
+
+                let client_error = client::ClientError::from_service(err, "message".to_string());
+                let expected = match err {
+                    // A malformed request is a bug of the caller, not of the node it called
+                    ServiceError::InvalidArgument => ServiceError::Unexpected,
+                    err => err,
+                };
+                assert_eq!(expected, ServiceError::from(client_error));
+            }
+            assert_eq!(None, ServiceError::from_code("unknown"));
+        }
+    }
 }
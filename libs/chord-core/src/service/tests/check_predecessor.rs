@@ -1,7 +1,7 @@
 use crate::client::{ClientError, MockClient};
 use crate::service::tests::{self, ExpectationExt};
 use crate::service::tests::{get_lock, MTX};
-use crate::{NodeId, NodeService};
+use crate::{Address, NodeId, NodeService};
 use std::net::SocketAddr;
 
 #[tokio::test]
@@ -9,12 +9,12 @@ async fn when_predecessor_is_up_it_should_not_be_removed() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42012 {
             client.expect_ping().times(1).returning(|| Ok(()));
         }
-        client
+        Ok(client)
     });
 
     let service: NodeService<MockClient> =
@@ -33,7 +33,7 @@ async fn when_predecessor_is_down_it_should_be_removed() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let client = MockClient::mock(addr, 10, |mut client| {
             client
                 .expect_ping()
@@ -43,7 +43,7 @@ async fn when_predecessor_is_down_it_should_be_removed() {
             client
         });
 
-        client
+        Ok(client)
     });
 
     let service: NodeService<MockClient> =
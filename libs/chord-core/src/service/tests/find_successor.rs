@@ -1,9 +1,9 @@
 use mockall::predicate;
 
-use crate::client::MockClient;
+use crate::client::{ClientError, MockClient};
 use crate::service::tests::{self, ExpectationExt};
 use crate::service::tests::{get_lock, MTX};
-use crate::{NodeId, NodeService};
+use crate::{Address, NodeId, NodeService};
 use std::net::SocketAddr;
 
 #[tokio::test]
@@ -23,13 +23,13 @@ async fn find_successor_with_2_nodes() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|_| {
+    ctx.expect().returning(|_, _| {
         let mut client = MockClient::new();
         client
             .expect_find_successor()
             .times(1)
             .returning(|_| Ok(tests::node(6)));
-        client
+        Ok(client)
     });
 
     let mut service: NodeService<MockClient> =
@@ -52,7 +52,7 @@ async fn find_successor_with_2_nodes_but_the_same_id() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42006 {
             client
@@ -60,7 +60,7 @@ async fn find_successor_with_2_nodes_but_the_same_id() {
                 .times(1)
                 .returning(|_| Ok(tests::node(6)));
         }
-        client
+        Ok(client)
     });
 
     let service: NodeService<MockClient> =
@@ -83,7 +83,7 @@ async fn find_successor_using_finger_table_nodes() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42035 {
             client
@@ -98,7 +98,7 @@ async fn find_successor_using_finger_table_nodes() {
                 .times(1)
                 .returning(|_| Ok(tests::node(5)));
         }
-        client
+        Ok(client)
     });
 
     let mut service: NodeService<MockClient> = NodeService::default();
@@ -131,7 +131,7 @@ async fn find_successor_using_finger_table() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42010 {
             client
@@ -161,7 +161,7 @@ async fn find_successor_using_finger_table() {
                 crate::client::ClientError::ConnectionFailed("Error".to_string()),
             );
         }
-        client
+        Ok(client)
     });
 
     let mut service: NodeService<MockClient> = NodeService::default();
@@ -182,7 +182,7 @@ async fn find_successor_using_finger_table_and_all_fingers_failing() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42008 {
             client.expect_find_successor().times(1).returning_error(
@@ -204,7 +204,7 @@ async fn find_successor_using_finger_table_and_all_fingers_failing() {
                 ));
         }
 
-        client
+        Ok(client)
     });
 
     let mut service: NodeService<MockClient> = NodeService::default();
@@ -257,3 +257,113 @@ async fn find_successor_immediate_successor_list() {
         None
     );
 }
+
+#[tokio::test]
+async fn find_successor_uses_the_cached_owner_of_the_range() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42032 {
+            client.mock_find_successor(NodeId(40), 64);
+        }
+        Ok(client)
+    });
+
+    let mut service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
+    service.with_fingers(vec![8, 16, 32, 64]);
+    service.store.db().set_successor(tests::node(16));
+
+    assert_eq!(
+        service.find_successor(NodeId(40)).await.unwrap().id,
+        NodeId(64)
+    );
+    assert_eq!(
+        service.find_successor(NodeId(40)).await.unwrap().id,
+        NodeId(64)
+    );
+    assert_eq!(
+        service.find_successor(NodeId(50)).await.unwrap().id,
+        NodeId(64)
+    );
+}
+
+#[tokio::test]
+async fn find_successor_looks_up_the_owner_again_when_it_fails() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42032 {
+            client
+                .expect_find_successor()
+                .with(predicate::eq(NodeId(40)))
+                .times(2)
+                .returning(|_| Ok(tests::node(64)));
+        }
+        Ok(client)
+    });
+
+    let mut service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
+    service.with_fingers(vec![8, 16, 32, 64]);
+    service.store.db().set_successor(tests::node(16));
+
+    let owner = service.find_successor(NodeId(40)).await.unwrap();
+    service.client_failed(&owner, &ClientError::ConnectionFailed("down".into()));
+
+    assert_eq!(
+        service.find_successor(NodeId(40)).await.unwrap().id,
+        NodeId(64)
+    );
+}
+
+#[tokio::test]
+async fn find_successor_uncached_finds_a_node_which_joined_within_a_cached_range() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42032 {
+            client
+                .expect_find_successor()
+                .with(predicate::eq(NodeId(40)))
+                .times(1)
+                .returning(|_| Ok(tests::node(64)));
+            // Node 48 joins between 32 and 64
+            client
+                .expect_find_successor()
+                .with(predicate::eq(NodeId(40)))
+                .times(1)
+                .returning(|_| Ok(tests::node(48)));
+        }
+        Ok(client)
+    });
+
+    let mut service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
+    service.with_fingers(vec![8, 16, 32, 64]);
+    service.store.db().set_successor(tests::node(16));
+
+    assert_eq!(
+        service.find_successor(NodeId(40)).await.unwrap().id,
+        NodeId(64)
+    );
+    assert_eq!(
+        service
+            .find_successor_uncached(NodeId(40))
+            .await
+            .unwrap()
+            .id,
+        NodeId(48)
+    );
+    // The range of the new node replaced the cached one
+    assert_eq!(
+        service.find_successor(NodeId(40)).await.unwrap().id,
+        NodeId(48)
+    );
+}
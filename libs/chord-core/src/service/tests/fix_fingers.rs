@@ -1,6 +1,6 @@
 use crate::client::MockClient;
-use crate::service::tests::{get_lock, MTX};
-use crate::{NodeId, NodeService};
+use crate::service::tests::{self, get_lock, MTX};
+use crate::{Address, FixFingers, NodeId, NodeService};
 use std::net::SocketAddr;
 
 #[tokio::test]
@@ -8,7 +8,7 @@ async fn fix_fingers_test() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42014 {
             client.mock_find_successor(NodeId(16), 19);
@@ -20,7 +20,7 @@ async fn fix_fingers_test() {
             client.mock_find_successor(NodeId(40), 42);
         }
 
-        client
+        Ok(client)
     });
     let mut service: NodeService<MockClient> =
         NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
@@ -40,3 +40,58 @@ async fn fix_fingers_test() {
     // );
     // assert_eq!(service.collect_finger_ids(), vec![9, 10, 12, 16, 24, 40]);
 }
+
+#[tokio::test]
+async fn incremental_strategy_fixes_one_finger_per_call() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42016 {
+            client.expect_successor_list().returning(|| Ok(vec![]));
+        }
+        Ok(client)
+    });
+
+    let mut service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
+    service.fix_fingers = FixFingers::Incremental;
+    service.store.db().set_successor(tests::node(16));
+
+    service.fix_fingers().await;
+    service.fix_fingers().await;
+
+    let finger_ids = service.collect_finger_node_ids();
+    assert_eq!(finger_ids[..3], [16, 16, 8]);
+}
+
+#[tokio::test]
+async fn skip_covered_strategy_only_looks_up_fingers_past_the_previous_one() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42016 {
+            client.mock_find_successor(NodeId(24), 64);
+            client.expect_successor_list().returning(|| Ok(vec![]));
+        }
+        if addr.port() == 42064 {
+            client.mock_find_successor(NodeId(72), 8);
+            client.expect_successor_list().returning(|| Ok(vec![]));
+        }
+        Ok(client)
+    });
+
+    let service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
+    service.store.db().set_successor(tests::node(16));
+
+    service.fix_fingers().await;
+
+    let mut finger_ids = vec![16; 4];
+    finger_ids.append(&mut vec![64; 2]);
+    finger_ids.append(&mut vec![8; 58]);
+    assert_eq!(service.collect_finger_node_ids(), finger_ids);
+}
@@ -1,7 +1,8 @@
 use crate::client::{ClientError, MockClient};
+use crate::error::ServiceError;
 use crate::service::tests::{self, ExpectationExt};
 use crate::service::tests::{get_lock, MTX};
-use crate::{NodeId, NodeService};
+use crate::{Address, NodeId, NodeService};
 use mockall::predicate;
 use std::net::SocketAddr;
 
@@ -10,7 +11,7 @@ async fn join_test() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42115 {
             client
@@ -20,7 +21,7 @@ async fn join_test() {
                 .returning(|_| Ok(tests::node(115)));
         }
 
-        client
+        Ok(client)
     });
     let service: NodeService<MockClient> =
         NodeService::with_id(1, SocketAddr::from(([127, 0, 0, 1], 42001)), 3);
@@ -35,7 +36,7 @@ async fn join_error_test() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42116 {
             client
@@ -44,7 +45,7 @@ async fn join_error_test() {
                 .times(1)
                 .returning_error(ClientError::Unexpected);
         }
-        client
+        Ok(client)
     });
     let service: NodeService<MockClient> =
         NodeService::with_id(2, SocketAddr::from(([127, 0, 0, 1], 42001)), 3);
@@ -53,3 +54,67 @@ async fn join_error_test() {
 
     assert!(result.is_err());
 }
+
+#[tokio::test]
+async fn join_with_the_id_of_another_node_is_rejected() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42115 {
+            client
+                .expect_find_successor()
+                .with(predicate::eq(NodeId(3)))
+                .times(1)
+                .returning(|_| Ok(tests::node(3)));
+        }
+        if addr.port() == 42003 {
+            client.expect_ping().times(1).returning(|| Ok(()));
+        }
+        Ok(client)
+    });
+    let service: NodeService<MockClient> =
+        NodeService::with_id(3, SocketAddr::from(([127, 0, 0, 1], 43003)), 3);
+
+    let report = service.join(tests::node(115)).await.unwrap_err();
+
+    assert!(matches!(
+        report.current_context(),
+        ServiceError::DuplicateId
+    ));
+    assert_eq!(service.store.db().successor().id, NodeId(3));
+    assert_eq!(service.store.db().successor().addr, service.addr);
+}
+
+#[tokio::test]
+async fn join_with_the_id_of_a_node_which_is_down_fails() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42115 {
+            client
+                .expect_find_successor()
+                .times(1)
+                .returning(|_| Ok(tests::node(3)));
+        }
+        if addr.port() == 42003 {
+            client
+                .expect_ping()
+                .times(1)
+                .returning_error(ClientError::ConnectionFailed("down".into()));
+        }
+        Ok(client)
+    });
+    let service: NodeService<MockClient> =
+        NodeService::with_id(3, SocketAddr::from(([127, 0, 0, 1], 43003)), 3);
+
+    let report = service.join(tests::node(115)).await.unwrap_err();
+
+    assert!(matches!(
+        report.current_context(),
+        ServiceError::Unavailable
+    ));
+}
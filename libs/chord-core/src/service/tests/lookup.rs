@@ -0,0 +1,192 @@
+use crate::client::{ClientError, MockClient};
+use crate::lookup::{NextHop, PrecedingFinger};
+use crate::service::tests;
+use crate::service::tests::{get_lock, MTX};
+use crate::{Address, NodeId, NodeService};
+use error_stack::Report;
+use mockall::predicate;
+use std::net::SocketAddr;
+
+#[tokio::test]
+async fn lookup_of_an_id_before_the_successor_has_no_hops() {
+    let _m = get_lock(&MTX);
+    let service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42001)), 3);
+    service.store.db().set_successor(tests::node(16));
+
+    let lookup = service.lookup(NodeId(10)).await.unwrap();
+
+    assert_eq!(lookup.owner.id, NodeId(16));
+    assert_eq!(lookup.path.len(), 1);
+    assert_eq!(lookup.path[0].node.id, NodeId(8));
+}
+
+#[tokio::test]
+async fn lookup_follows_the_closest_preceding_finger() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42032 {
+            client
+                .expect_next_hop()
+                .with(predicate::eq(NodeId(40)))
+                .times(1)
+                .returning(|_| {
+                    Ok(NextHop {
+                        node: tests::node(32),
+                        successor: tests::node(64),
+                        finger: None,
+                    })
+                });
+        }
+        Ok(client)
+    });
+
+    let mut service: NodeService<MockClient> = NodeService::default();
+    service.with_fingers(vec![8, 16, 32, 64]);
+    service.store.db().set_successor(tests::node(16));
+
+    let lookup = service.lookup(NodeId(40)).await.unwrap();
+
+    assert_eq!(lookup.owner.id, NodeId(64));
+    let path: Vec<_> = lookup
+        .path
+        .iter()
+        .map(|hop| (hop.node.id.0, hop.finger))
+        .collect();
+    assert_eq!(path, vec![(8, None), (32, Some(4))]);
+}
+
+#[tokio::test]
+async fn lookup_routes_around_a_failing_finger_through_the_successor() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42032 {
+            client
+                .expect_next_hop()
+                .times(1)
+                .returning(|_| Err(Report::new(ClientError::ConnectionFailed("down".into()))));
+        }
+        if addr.port() == 42016 {
+            client.expect_next_hop().times(1).returning(|_| {
+                Ok(NextHop {
+                    node: tests::node(16),
+                    successor: tests::node(32),
+                    finger: Some(PrecedingFinger {
+                        index: 4,
+                        node: tests::node(35),
+                    }),
+                })
+            });
+        }
+        if addr.port() == 42035 {
+            client.expect_next_hop().times(1).returning(|_| {
+                Ok(NextHop {
+                    node: tests::node(35),
+                    successor: tests::node(64),
+                    finger: None,
+                })
+            });
+        }
+        Ok(client)
+    });
+
+    let mut service: NodeService<MockClient> = NodeService::default();
+    service.with_fingers(vec![8, 16, 32, 64]);
+    service.store.db().set_successor(tests::node(16));
+
+    let lookup = service.lookup(NodeId(40)).await.unwrap();
+
+    assert_eq!(lookup.owner.id, NodeId(64));
+    let path: Vec<_> = lookup
+        .path
+        .iter()
+        .map(|hop| (hop.node.id.0, hop.finger))
+        .collect();
+    assert_eq!(path, vec![(8, None), (16, None), (35, Some(4))]);
+}
+
+#[tokio::test]
+async fn lookup_routes_around_an_overloaded_finger() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42032 {
+            client
+                .expect_next_hop()
+                .times(1)
+                .returning(|_| Err(Report::new(ClientError::Overloaded("busy".into()))));
+        }
+        if addr.port() == 42016 {
+            client.expect_next_hop().times(1).returning(|_| {
+                Ok(NextHop {
+                    node: tests::node(16),
+                    successor: tests::node(32),
+                    finger: Some(PrecedingFinger {
+                        index: 4,
+                        node: tests::node(35),
+                    }),
+                })
+            });
+        }
+        if addr.port() == 42035 {
+            client.expect_next_hop().times(1).returning(|_| {
+                Ok(NextHop {
+                    node: tests::node(35),
+                    successor: tests::node(64),
+                    finger: None,
+                })
+            });
+        }
+        Ok(client)
+    });
+
+    let mut service: NodeService<MockClient> = NodeService::default();
+    service.with_fingers(vec![8, 16, 32, 64]);
+    service.store.db().set_successor(tests::node(16));
+
+    let lookup = service.lookup(NodeId(40)).await.unwrap();
+
+    assert_eq!(lookup.owner.id, NodeId(64));
+    let path: Vec<_> = lookup
+        .path
+        .iter()
+        .map(|hop| (hop.node.id.0, hop.finger))
+        .collect();
+    assert_eq!(path, vec![(8, None), (16, None), (35, Some(4))]);
+}
+
+#[tokio::test]
+async fn lookup_fails_when_no_hop_responds() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42032 || addr.port() == 42016 {
+            client
+                .expect_next_hop()
+                .times(1)
+                .returning(|_| Err(Report::new(ClientError::ConnectionFailed("down".into()))));
+        }
+        Ok(client)
+    });
+
+    let mut service: NodeService<MockClient> = NodeService::default();
+    service.with_fingers(vec![8, 16, 32, 64]);
+    service.store.db().set_successor(tests::node(16));
+
+    let report = service.lookup(NodeId(40)).await.unwrap_err();
+
+    assert!(matches!(
+        report.current_context(),
+        crate::error::ServiceError::Unavailable
+    ));
+}
@@ -0,0 +1,124 @@
+use crate::client::{ClientError, MockClient};
+use crate::service::tests::{self, ExpectationExt};
+use crate::service::tests::{get_lock, MTX};
+use crate::{Address, NodeId, NodeService};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn fix_fingers_keeps_the_successors_of_a_finger_as_its_candidates() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42016 {
+            client
+                .expect_find_successor()
+                .returning(|_| Ok(tests::node(16)));
+            client
+                .expect_successor_list()
+                .times(1)
+                .returning(|| Ok(vec![tests::node(20), tests::node(23), tests::node(30)]));
+        }
+        Ok(client)
+    });
+
+    let service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
+    service.store.db().set_successor(tests::node(16));
+    service.store.db().update_finger(0, tests::node(16));
+
+    service.fix_fingers().await;
+
+    // Finger 3 covers [16, 24)
+    let candidates: Vec<_> = service.finger_table()[3]
+        .candidates
+        .iter()
+        .map(|candidate| candidate.node.id)
+        .collect();
+    assert_eq!(candidates, vec![NodeId(16), NodeId(20), NodeId(23)]);
+}
+
+#[tokio::test]
+async fn measure_fingers_records_rtt_and_removes_candidates_which_are_down() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+
+    ctx.expect().returning(|addr: Address, _| {
+        let mut client = MockClient::new();
+        if addr.port() == 42016 {
+            client.expect_ping().times(1).returning(|| Ok(()));
+        }
+        if addr.port() == 42020 {
+            client
+                .expect_ping()
+                .times(1)
+                .returning_error(ClientError::ConnectionFailed("down".into()));
+        }
+        Ok(client)
+    });
+
+    let service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
+    service.store.db().update_finger(3, tests::node(16));
+    service
+        .store
+        .db()
+        .set_finger_candidates(3, vec![tests::node(20)]);
+
+    service.measure_fingers().await;
+
+    let candidates = &service.finger_table()[3].candidates;
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].node.id, NodeId(16));
+    assert!(candidates[0].rtt.is_some());
+}
+
+#[tokio::test]
+async fn measure_fingers_pings_a_bounded_number_of_candidates_per_call() {
+    let _m = get_lock(&MTX);
+    let ctx = MockClient::init_context();
+    let pings = Arc::new(AtomicUsize::new(0));
+
+    let counter = pings.clone();
+    ctx.expect().returning(move |_, _| {
+        let mut client = MockClient::new();
+        let counter = counter.clone();
+        client.expect_ping().returning(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+        Ok(client)
+    });
+
+    let service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42008)), 3);
+    for finger in 0..6 {
+        service
+            .store
+            .db()
+            .update_finger(finger, tests::node(16 + finger as u64));
+        service
+            .store
+            .db()
+            .set_finger_candidates(finger, vec![tests::node(32 + finger as u64)]);
+    }
+
+    service.measure_fingers().await;
+    assert_eq!(
+        pings.load(Ordering::SeqCst),
+        NodeService::<MockClient>::MEASURED_CANDIDATES
+    );
+
+    // The second call measures the remaining 4 candidates and wraps around
+    service.measure_fingers().await;
+    assert_eq!(pings.load(Ordering::SeqCst), 16);
+    assert!(service
+        .finger_table()
+        .iter()
+        .flat_map(|finger| finger.candidates.iter())
+        .filter(|candidate| candidate.node.id != service.id())
+        .all(|candidate| candidate.rtt.is_some()));
+}
@@ -2,13 +2,17 @@ use crate::client::__mock_MockClient_Client::{
     __find_successor, __ping, __predecessor, __successor_list,
 };
 use crate::client::{self, ClientsPool, MockClient};
-use crate::{Node, NodeId, NodeService};
+use crate::lookup::LookupCache;
+use crate::{Address, FixFingers, Node, NodeId, NodeService};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU8, AtomicUsize};
 
 mod check_predecessor;
 mod find_successor;
 mod fix_fingers;
 mod join;
+mod lookup;
+mod measure_fingers;
 mod notify;
 mod reconcile_successors;
 mod stabilize;
@@ -48,7 +52,13 @@ impl Default for NodeService<MockClient> {
         Self {
             id: node.id,
             addr: node.addr,
+            host: None,
             store,
+            secret: None,
+            fix_fingers: FixFingers::default(),
+            next_finger: AtomicU8::new(0),
+            next_candidate: AtomicUsize::new(0),
+            cache: LookupCache::default(),
             clients: ClientsPool::default(),
         }
     }
@@ -61,7 +71,13 @@ impl NodeService<MockClient> {
         Self {
             id: node.id,
             addr: node.addr,
+            host: None,
             store,
+            secret: None,
+            fix_fingers: FixFingers::default(),
+            next_finger: AtomicU8::new(0),
+            next_candidate: AtomicUsize::new(0),
+            cache: LookupCache::default(),
             clients: ClientsPool::default(),
         }
     }
@@ -138,12 +154,12 @@ impl MockClient {
     /// let _m = get_lock(&MTX);
     /// let ctx = MockClient::init_context();
     ///
-    /// ctx.expect().returning(|addr: SocketAddr| {
+    /// ctx.expect().returning(|addr: Address, _| {
     ///     let mut client = MockClient::new();
     ///     // Node with port 42014 will respond with 21 as a successor for id 16.
     ///     if addr.port() == 42014 { client.mock_find_successor(16, 21); }
     ///
-    ///     client
+    ///     Ok(client)
     /// });
     /// ```
     fn mock_find_successor(&mut self, id: NodeId, return_node: u64) {
@@ -184,7 +200,7 @@ impl ExpectationExt<client::ClientError> for __successor_list::Expectation {
 
 impl MockClient {
     pub fn mock(
-        addr: SocketAddr,
+        addr: Address,
         node_id: u64,
         mock_fn: impl FnOnce(MockClient) -> MockClient,
     ) -> Self {
@@ -200,6 +216,44 @@ impl MockClient {
 
 mod tests {
     use super::*;
+    use crate::ServiceConfig;
+
+    #[test]
+    fn test_advertised_address_is_the_identity_of_the_node() {
+        let listen = SocketAddr::from(([0, 0, 0, 0], 42000));
+        let advertise = SocketAddr::from(([10, 0, 0, 1], 42000));
+
+        let service: NodeService<MockClient> = NodeService::with_config(
+            listen,
+            3,
+            ServiceConfig::default().advertise(Some(advertise)),
+        );
+
+        assert_eq!(service.addr(), advertise);
+        assert_eq!(service.id(), NodeId::from(advertise));
+        assert_eq!(service.store.db().successor().addr, advertise);
+    }
+
+    #[test]
+    fn test_host_name_is_the_identity_of_the_node() {
+        let advertise = SocketAddr::from(([10, 0, 0, 1], 42000));
+        let config = ServiceConfig::default()
+            .advertise(Some(advertise))
+            .host(Some("chord-0.chord".to_string()));
+
+        let service: NodeService<MockClient> =
+            NodeService::with_config(advertise, 3, config.clone());
+        let moved: NodeService<MockClient> = NodeService::with_config(
+            SocketAddr::from(([10, 0, 0, 2], 42000)),
+            3,
+            config.advertise(None),
+        );
+
+        assert_eq!(service.id(), NodeId::from_name("chord-0.chord:42000"));
+        assert_eq!(service.id(), moved.id());
+        assert_eq!(service.node().host(), Some("chord-0.chord"));
+        assert_eq!(service.store.db().successor(), service.node());
+    }
 
     #[test]
     fn test_finger_table() {
@@ -1,4 +1,6 @@
+use crate::auth::ClusterSecret;
 use crate::client::MockClient;
+use crate::error::ServiceError;
 use crate::service::tests;
 use crate::{NodeId, NodeService};
 use std::net::SocketAddr;
@@ -10,7 +12,7 @@ fn when_calling_notify_and_predecessor_is_none_then_the_predecessor_should_be_se
     service.store.db().set_successor(tests::node(16));
 
     assert!(service.store.db().predecessor().is_none());
-    service.notify(tests::node(8));
+    service.notify(tests::node(8), None).unwrap();
 
     assert_eq!(service.store.db().predecessor().unwrap().id, NodeId(8));
 }
@@ -24,7 +26,7 @@ fn when_calling_notify_and_predecessor_set_and_request_node_is_in_range_then_the
     service.store.db().set_predecessor(tests::node(4));
 
     assert!(service.store.db().predecessor().is_some());
-    service.notify(tests::node(8));
+    service.notify(tests::node(8), None).unwrap();
 
     assert_eq!(service.store.db().predecessor().unwrap().id, NodeId(8));
 }
@@ -38,7 +40,63 @@ fn when_calling_notify_and_predecessor_set_and_request_node_is_not_in_range_then
     service.store.db().set_predecessor(tests::node(4));
 
     assert!(service.store.db().predecessor().is_some());
-    service.notify(tests::node(16));
+    service.notify(tests::node(16), None).unwrap();
 
     assert_eq!(service.store.db().predecessor().unwrap().id, NodeId(4));
 }
+
+#[test]
+fn when_calling_notify_without_a_valid_token_and_secret_is_set_then_the_call_should_be_rejected() {
+    let mut service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42001)), 3);
+    service.secret = Some(ClusterSecret::new("secret"));
+    service.store.db().set_successor(tests::node(16));
+
+    let result = service.notify(tests::node(8), None);
+    assert!(matches!(
+        result.unwrap_err().current_context(),
+        ServiceError::Unauthenticated
+    ));
+
+    let token = ClusterSecret::new("other").sign(&tests::node(8));
+    let result = service.notify(tests::node(8), Some(&token));
+    assert!(matches!(
+        result.unwrap_err().current_context(),
+        ServiceError::Unauthenticated
+    ));
+
+    assert!(service.store.db().predecessor().is_none());
+}
+
+#[test]
+fn when_calling_notify_with_a_valid_token_and_secret_is_set_then_the_predecessor_should_be_set() {
+    let secret = ClusterSecret::new("secret");
+    let mut service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42001)), 3);
+    service.secret = Some(secret.clone());
+    service.store.db().set_successor(tests::node(16));
+
+    let token = secret.sign(&tests::node(8));
+    service.notify(tests::node(8), Some(&token)).unwrap();
+
+    assert_eq!(service.store.db().predecessor().unwrap().id, NodeId(8));
+}
+
+#[test]
+fn when_calling_notify_with_a_node_which_cannot_be_reached_then_the_call_should_be_rejected() {
+    let service: NodeService<MockClient> =
+        NodeService::with_id(8, SocketAddr::from(([127, 0, 0, 1], 42001)), 3);
+    service.store.db().set_successor(tests::node(16));
+
+    let node = tests::node(4).with_host(Some("not a host".to_string()));
+    let result = service.notify(node, None);
+    assert!(matches!(
+        result.unwrap_err().current_context(),
+        ServiceError::InvalidArgument
+    ));
+
+    let node = crate::Node::with_id(4, SocketAddr::from(([127, 0, 0, 1], 0)));
+    assert!(service.notify(node, None).is_err());
+
+    assert!(service.store.db().predecessor().is_none());
+}
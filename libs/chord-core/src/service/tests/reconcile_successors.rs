@@ -1,15 +1,14 @@
 use crate::client::{ClientError, MockClient};
 use crate::service::tests::{self, ExpectationExt};
 use crate::service::tests::{get_lock, MTX};
-use crate::{NodeId, NodeService};
-use std::net::SocketAddr;
+use crate::{Address, NodeId, NodeService};
 
 #[tokio::test]
 async fn test_updating_successor_list_from_successor() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42016 {
             client
@@ -21,7 +20,7 @@ async fn test_updating_successor_list_from_successor() {
                 .returning(|| Ok(vec![tests::node(32), tests::node(64), tests::node(128)]));
         }
         client.expect_notify().returning(|_| Ok(()));
-        client
+        Ok(client)
     });
 
     let service = NodeService::test_service(90);
@@ -43,7 +42,7 @@ async fn test_updating_successor_list_from_successor_which_returns_only_one_node
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42016 {
             client
@@ -55,7 +54,7 @@ async fn test_updating_successor_list_from_successor_which_returns_only_one_node
                 .returning(|| Ok(vec![tests::node(32)]));
         }
         client.expect_notify().returning(|_| Ok(()));
-        client
+        Ok(client)
     });
 
     let service = NodeService::test_service(90);
@@ -76,7 +75,7 @@ async fn test_updating_successor_list_from_successor_which_returns_too_many_node
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42016 {
             client
@@ -93,7 +92,7 @@ async fn test_updating_successor_list_from_successor_which_returns_too_many_node
             });
         }
         client.expect_notify().returning(|_| Ok(()));
-        client
+        Ok(client)
     });
 
     let service = NodeService::test_service(90);
@@ -115,7 +114,7 @@ async fn test_updating_successor_list_with_successor_failing_to_respond() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42016 {
             client
@@ -132,7 +131,7 @@ async fn test_updating_successor_list_with_successor_failing_to_respond() {
                 .returning(|| Ok(vec![tests::node(64)]));
         }
         client.expect_notify().returning(|_| Ok(()));
-        client
+        Ok(client)
     });
 
     let service = NodeService::test_service(90);
@@ -159,7 +158,7 @@ async fn test_updating_successor_list_with_failing_node_as_successor() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42016 {
             client
@@ -176,7 +175,7 @@ async fn test_updating_successor_list_with_failing_node_as_successor() {
                 .returning(|| Ok(vec![tests::node(64)]));
         }
         client.expect_notify().returning(|_| Ok(()));
-        client
+        Ok(client)
     });
 
     let service = NodeService::test_service(90);
@@ -1,7 +1,7 @@
 use crate::client::{ClientError, MockClient};
 use crate::service::tests::{self, ExpectationExt};
 use crate::service::tests::{get_lock, MTX};
-use crate::{Node, NodeId, NodeService};
+use crate::{Address, Node, NodeId, NodeService};
 use mockall::predicate;
 use std::net::SocketAddr;
 
@@ -11,7 +11,7 @@ async fn stabilize_when_predecessor_is_between_node_and_successor_then_set_set_t
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42016 {
             client
@@ -27,7 +27,7 @@ async fn stabilize_when_predecessor_is_between_node_and_successor_then_set_set_t
                 .times(1)
                 .returning(|_| Ok(()));
         }
-        client
+        Ok(client)
     });
 
     let service: NodeService<MockClient> =
@@ -46,7 +46,7 @@ async fn when_predecessor_is_not_between_node_and_successor_then_the_old_one_sho
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|addr: SocketAddr| {
+    ctx.expect().returning(|addr: Address, _| {
         let mut client = MockClient::new();
         if addr.port() == 42016 {
             client
@@ -57,7 +57,7 @@ async fn when_predecessor_is_not_between_node_and_successor_then_the_old_one_sho
                 .with(predicate::function(|n: &Node| n.id == NodeId(8)))
                 .returning(|_| Ok(()));
         }
-        client
+        Ok(client)
     });
 
     let service: NodeService<MockClient> =
@@ -76,7 +76,7 @@ fn when_getting_predecessor_fails_then_nothing_should_be_updated() {
     let _m = get_lock(&MTX);
     let ctx = MockClient::init_context();
 
-    ctx.expect().returning(|_| {
+    ctx.expect().returning(|_, _| {
         let mut client = MockClient::new();
         client
             .expect_predecessor()
@@ -85,7 +85,7 @@ fn when_getting_predecessor_fails_then_nothing_should_be_updated() {
             .expect_notify()
             .with(predicate::function(|n: &Node| n.id == NodeId(8)))
             .returning(|_| Ok(()));
-        client
+        Ok(client)
     });
 
     let service: NodeService<MockClient> =
@@ -0,0 +1,615 @@
+//! Conformance tests for [`Client`] implementations
+//!
+//! The suite starts small rings on loopback, where every node is exposed by the transport
+//! under test, and checks that the client returns the same results as the node services
+//! behind it. Transports run it with the [`client_conformance_tests`] macro.
+//!
+//! [`client_conformance_tests`]: crate::client_conformance_tests
+
+use std::marker::PhantomData;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::auth::ClusterSecret;
+use crate::client::{ClientConfig, ClientError, ClientsPool, Timeouts, TlsConfig};
+use crate::lookup::Lookup;
+use crate::{Address, Client, Node, NodeId, NodeService};
+
+pub mod tls;
+
+use tls::TlsFixture;
+
+/// Starts a server exposing a node service
+///
+/// The server has to run in the background, e.g. in a spawned task or thread,
+/// and listen on the given address.
+pub trait ServerFactory<C: Client>: Send + Sync {
+    /// Start serving the node service
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address to listen on
+    /// * `node` - The node service to expose
+    fn start(&self, addr: SocketAddr, node: Arc<NodeService<C>>);
+}
+
+impl<C, F> ServerFactory<C> for F
+where
+    C: Client,
+    F: Fn(SocketAddr, Arc<NodeService<C>>) + Send + Sync,
+{
+    fn start(&self, addr: SocketAddr, node: Arc<NodeService<C>>) {
+        self(addr, node)
+    }
+}
+
+/// Ring of nodes running on loopback, sorted by node id
+pub struct Ring<C: Client> {
+    nodes: Vec<Arc<NodeService<C>>>,
+    addrs: Vec<SocketAddr>,
+    client_config: ClientConfig,
+}
+
+impl<C: Client + Clone + Sync + Send + 'static> Ring<C> {
+    /// Get the node at the given position, the position wraps around the ring
+    pub fn node(&self, i: usize) -> Node {
+        let i = i % self.nodes.len();
+        Node::with_id(self.nodes[i].id(), self.addrs[i])
+    }
+
+    /// Get the node service at the given position
+    pub fn service(&self, i: usize) -> &Arc<NodeService<C>> {
+        &self.nodes[i % self.nodes.len()]
+    }
+
+    /// Create a client for the node at the given position
+    pub async fn client(&self, i: usize) -> C {
+        C::init(self.node(i).address(), self.client_config.clone())
+            .await
+            .expect("Failed to create a client")
+    }
+
+    /// Get the node which is responsible for the given id
+    pub fn owner(&self, id: NodeId) -> Node {
+        let i = self
+            .nodes
+            .iter()
+            .position(|node| node.id() >= id)
+            .unwrap_or(0);
+        self.node(i)
+    }
+
+    /// Get the number of nodes in the ring
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+/// Generic test suite for [`Client`] implementations
+///
+/// Each check panics when the client doesn't conform.
+pub struct ConformanceSuite<C: Client, F: ServerFactory<C>> {
+    factory: F,
+    client_config: ClientConfig,
+    /// Certificates used by the servers and the clients, kept until the suite is dropped
+    _tls: Option<TlsFixture>,
+    _client: PhantomData<C>,
+}
+
+impl<C, F> ConformanceSuite<C, F>
+where
+    C: Client + Clone + Sync + Send + 'static,
+    F: ServerFactory<C>,
+{
+    const REPLICATION_FACTOR: usize = 3;
+    const STABILIZE_ROUNDS: usize = 3;
+
+    pub fn new(factory: F) -> Self {
+        Self::with_config(factory, ClientConfig::default())
+    }
+
+    /// Create a suite, which uses the given config for all the clients
+    ///
+    /// # Arguments
+    ///
+    /// * `factory` - Starts the servers of the transport under test
+    /// * `client_config` - The config used by the clients, both of the suite and of the nodes
+    pub fn with_config(factory: F, client_config: ClientConfig) -> Self {
+        Self {
+            factory,
+            client_config,
+            _tls: None,
+            _client: PhantomData,
+        }
+    }
+
+    /// Create a suite, whose servers and clients use the certificates of a new [`TlsFixture`]
+    ///
+    /// # Arguments
+    ///
+    /// * `factory` - Creates the factory of the servers, which use the given TLS config
+    pub fn with_tls(factory: impl FnOnce(TlsConfig) -> F) -> Self {
+        let tls = TlsFixture::generate();
+        Self {
+            factory: factory(tls.node_config(true)),
+            client_config: tls.client_config(),
+            _tls: Some(tls),
+            _client: PhantomData,
+        }
+    }
+
+    /// Start a ring of the given size
+    ///
+    /// The nodes don't run the background tasks, instead the ring is stabilized
+    /// by the suite before it is returned, so the state of the nodes is known.
+    pub async fn ring(&self, size: usize) -> Ring<C> {
+        self.ring_with_config(size, self.client_config.clone())
+            .await
+    }
+
+    /// Start a ring of the given size, whose nodes and clients use the given config
+    pub async fn ring_with_config(&self, size: usize, client_config: ClientConfig) -> Ring<C> {
+        let mut nodes = Vec::with_capacity(size);
+        for _ in 0..size {
+            let addr = free_addr();
+            let node = Arc::new(NodeService::with_config(
+                addr,
+                Self::REPLICATION_FACTOR,
+                client_config.clone(),
+            ));
+            self.factory.start(addr, node.clone());
+            wait_for::<C>(addr, client_config.clone()).await;
+            nodes.push((addr, node));
+        }
+
+        let bootstrap = Node::new(nodes[0].0);
+        for (_, node) in nodes.iter().skip(1) {
+            node.join(bootstrap.clone())
+                .await
+                .expect("Failed to join the ring");
+        }
+
+        for _ in 0..Self::STABILIZE_ROUNDS * size {
+            for (_, node) in nodes.iter() {
+                node.stabilize().await.expect("Failed to stabilize");
+                node.reconcile_successors().await;
+            }
+        }
+        for (_, node) in nodes.iter() {
+            node.fix_fingers().await;
+        }
+
+        nodes.sort_by_key(|(_, node)| node.id());
+        let (addrs, nodes) = nodes.into_iter().unzip();
+        Ring {
+            nodes,
+            addrs,
+            client_config,
+        }
+    }
+
+    /// Run all the checks
+    pub async fn run(&self) {
+        self.ping().await;
+        self.find_successor().await;
+        self.next_hop().await;
+        self.lookup().await;
+        self.successor().await;
+        self.successor_list().await;
+        self.predecessor().await;
+        self.notify().await;
+        self.host_name().await;
+        self.unauthenticated_notify().await;
+        self.invalid_notify().await;
+        self.timeout().await;
+        self.connection_failed().await;
+    }
+
+    pub async fn ping(&self) {
+        let ring = self.ring(1).await;
+
+        ring.client(0).await.ping().await.expect("Ping failed");
+    }
+
+    pub async fn find_successor(&self) {
+        let ring = self.ring(3).await;
+
+        for i in 0..ring.size() {
+            let client = ring.client(i).await;
+            for j in 0..ring.size() {
+                let id = ring.node(j).id();
+                for id in [
+                    id,
+                    NodeId(id.0.wrapping_sub(1)),
+                    NodeId(id.0.wrapping_add(1)),
+                ] {
+                    let successor = client
+                        .find_successor(id)
+                        .await
+                        .expect("Find successor failed");
+                    assert_eq!(
+                        ring.owner(id),
+                        successor,
+                        "successor of {} from node {}",
+                        id,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    pub async fn next_hop(&self) {
+        let ring = self.ring(3).await;
+
+        for i in 0..ring.size() {
+            let client = ring.client(i).await;
+            for j in 0..ring.size() {
+                let id = NodeId(ring.node(j).id().0.wrapping_sub(1));
+                let next_hop = client.next_hop(id).await.expect("Next hop failed");
+                assert_eq!(
+                    ring.service(i).next_hop(id),
+                    next_hop,
+                    "next hop to {} from node {}",
+                    id,
+                    i
+                );
+            }
+        }
+    }
+
+    /// Iterative lookups started by a client and by the nodes find the owner of every id
+    pub async fn lookup(&self) {
+        let ring = self.ring(4).await;
+        let clients = ClientsPool::<C>::new(self.client_config.clone());
+
+        for i in 0..ring.size() {
+            for j in 0..ring.size() {
+                let id = NodeId(ring.node(j).id().0.wrapping_sub(1));
+                let lookup = Lookup::run(&clients, id, ring.node(i))
+                    .await
+                    .expect("Lookup failed");
+                assert_eq!(
+                    ring.owner(id),
+                    lookup.owner,
+                    "owner of {} from node {}",
+                    id,
+                    i
+                );
+                assert_eq!(ring.node(i), lookup.path[0].node);
+
+                let lookup = ring.service(i).lookup(id).await.expect("Lookup failed");
+                assert_eq!(
+                    ring.owner(id),
+                    lookup.owner,
+                    "owner of {} from node {}",
+                    id,
+                    i
+                );
+            }
+        }
+    }
+
+    pub async fn successor(&self) {
+        let ring = self.ring(3).await;
+
+        for i in 0..ring.size() {
+            let successor = ring
+                .client(i)
+                .await
+                .successor()
+                .await
+                .expect("Get successor failed");
+            assert_eq!(ring.node(i + 1), successor);
+        }
+    }
+
+    pub async fn successor_list(&self) {
+        let ring = self.ring(3).await;
+
+        for i in 0..ring.size() {
+            let successors = ring
+                .client(i)
+                .await
+                .successor_list()
+                .await
+                .expect("Get successor list failed");
+            let expected = ring.service(i).get_successor_list().await.unwrap();
+
+            assert_eq!(expected, successors);
+            assert_eq!(ring.node(i + 1), successors[0]);
+        }
+    }
+
+    pub async fn predecessor(&self) {
+        let ring = self.ring(1).await;
+        // A single node becomes its own predecessor once it's stabilized
+        ring.service(0).store().unset_predecessor();
+        let predecessor = ring.client(0).await.predecessor().await;
+        assert_eq!(None, predecessor.expect("Get predecessor failed"));
+
+        let ring = self.ring(3).await;
+        for i in 0..ring.size() {
+            let predecessor = ring
+                .client(i)
+                .await
+                .predecessor()
+                .await
+                .expect("Get predecessor failed");
+            assert_eq!(Some(ring.node(i + ring.size() - 1)), predecessor);
+        }
+    }
+
+    pub async fn notify(&self) {
+        let ring = self.ring(1).await;
+        let predecessor = Node::new(free_addr());
+
+        let client = ring.client(0).await;
+        client
+            .notify(predecessor.clone())
+            .await
+            .expect("Notify failed");
+
+        assert_eq!(
+            Some(predecessor.clone()),
+            ring.service(0).get_predecessor().await.unwrap()
+        );
+        assert_eq!(Some(predecessor), client.predecessor().await.unwrap());
+    }
+
+    /// Nodes are reached by their host names, which are kept by the calls returning nodes
+    pub async fn host_name(&self) {
+        let ring = self.ring(1).await;
+        let addr = Address::Host("localhost".to_string(), ring.node(0).addr().port());
+
+        let client = C::init(addr, self.client_config.clone())
+            .await
+            .expect("Failed to create a client");
+        client.ping().await.expect("Ping by host name failed");
+
+        let predecessor = Node::new(free_addr()).with_host(Some("localhost".to_string()));
+        client
+            .notify(predecessor.clone())
+            .await
+            .expect("Notify failed");
+        assert_eq!(Some(predecessor), client.predecessor().await.unwrap());
+    }
+
+    /// Notify calls to a node with a cluster secret fail with [`ClientError::Unauthenticated`]
+    /// unless they are signed with the same secret
+    pub async fn unauthenticated_notify(&self) {
+        let secret = ClusterSecret::new("conformance secret");
+        let ring = self
+            .ring_with_config(1, self.client_config.clone().secret(Some(secret.clone())))
+            .await;
+        ring.service(0).store().unset_predecessor();
+
+        for (name, secret) in [
+            ("unsigned", None),
+            ("wrong secret", Some(ClusterSecret::new("other secret"))),
+        ] {
+            let config = self.client_config.clone().secret(secret);
+            let client = C::init(ring.node(0).address(), config)
+                .await
+                .expect("Failed to create a client");
+            let report = client.notify(Node::new(free_addr())).await.expect_err(name);
+            assert!(
+                matches!(report.current_context(), ClientError::Unauthenticated(_)),
+                "{} notify failed with {:?}",
+                name,
+                report
+            );
+            assert_eq!(None, ring.service(0).get_predecessor().await.unwrap());
+        }
+
+        let predecessor = Node::new(free_addr());
+        ring.client(0)
+            .await
+            .notify(predecessor.clone())
+            .await
+            .expect("Signed notify failed");
+        assert_eq!(
+            Some(predecessor),
+            ring.service(0).get_predecessor().await.unwrap()
+        );
+    }
+
+    /// Notify calls with a node which can't be connected to fail with
+    /// [`ClientError::InvalidRequest`]
+    pub async fn invalid_notify(&self) {
+        let ring = self.ring(1).await;
+        ring.service(0).store().unset_predecessor();
+        let client = ring.client(0).await;
+
+        for node in [
+            Node::new(SocketAddr::from(([127, 0, 0, 1], 0))),
+            Node::new(free_addr()).with_host(Some("not a host".to_string())),
+        ] {
+            let report = client.notify(node.clone()).await.expect_err("invalid node");
+            assert!(
+                matches!(report.current_context(), ClientError::InvalidRequest(_)),
+                "notify with {:?} failed with {:?}",
+                node,
+                report
+            );
+        }
+        assert_eq!(None, ring.service(0).get_predecessor().await.unwrap());
+    }
+
+    /// Calls to a node which doesn't respond fail with [`ClientError::Timeout`]
+    pub async fn timeout(&self) {
+        const TIMEOUT: Duration = Duration::from_millis(200);
+
+        let config = ClientConfig {
+            timeouts: Timeouts::all(TIMEOUT),
+            ..self.client_config.clone()
+        };
+        let client = C::init(unresponsive_listener().into(), config)
+            .await
+            .expect("Failed to create a client");
+
+        async fn assert_timeout<T: std::fmt::Debug>(
+            method: &str,
+            call: impl std::future::Future<Output = error_stack::Result<T, ClientError>>,
+        ) {
+            let report = tokio::time::timeout(TIMEOUT * 10, call)
+                .await
+                .unwrap_or_else(|_| panic!("{} didn't time out", method))
+                .expect_err(method);
+            assert!(
+                matches!(report.current_context(), ClientError::Timeout(_)),
+                "{} failed with {:?}",
+                method,
+                report
+            );
+        }
+
+        assert_timeout("ping", client.ping()).await;
+        assert_timeout("find_successor", client.find_successor(NodeId(1))).await;
+        assert_timeout("next_hop", client.next_hop(NodeId(1))).await;
+        assert_timeout("successor", client.successor()).await;
+        assert_timeout("successor_list", client.successor_list()).await;
+        assert_timeout("predecessor", client.predecessor()).await;
+        assert_timeout("notify", client.notify(Node::new(free_addr()))).await;
+    }
+
+    /// Calls to a node which is down fail with [`ClientError::ConnectionFailed`]
+    pub async fn connection_failed(&self) {
+        let client = C::init(free_addr().into(), self.client_config.clone())
+            .await
+            .expect("Failed to create a client");
+
+        fn assert_connection_failed<T: std::fmt::Debug>(
+            method: &str,
+            result: error_stack::Result<T, ClientError>,
+        ) {
+            let report = result.expect_err(method);
+            assert!(
+                matches!(report.current_context(), ClientError::ConnectionFailed(_)),
+                "{} failed with {:?}",
+                method,
+                report
+            );
+        }
+
+        assert_connection_failed("ping", client.ping().await);
+        assert_connection_failed("find_successor", client.find_successor(NodeId(1)).await);
+        assert_connection_failed("next_hop", client.next_hop(NodeId(1)).await);
+        assert_connection_failed("successor", client.successor().await);
+        assert_connection_failed("successor_list", client.successor_list().await);
+        assert_connection_failed("predecessor", client.predecessor().await);
+        let node = Node::new(free_addr());
+        assert_connection_failed("notify", client.notify(node).await);
+    }
+}
+
+/// Start a listener which accepts connections but never responds
+fn unresponsive_listener() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind a free port");
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let streams: Vec<_> = listener.incoming().collect();
+        drop(streams);
+    });
+
+    addr
+}
+
+/// Get a loopback address which is free at the moment
+pub fn free_addr() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind a free port");
+    listener.local_addr().unwrap()
+}
+
+/// Wait until the server on the given address responds to ping
+pub async fn wait_for<C: Client>(addr: SocketAddr, config: ClientConfig) {
+    const ATTEMPTS: usize = 100;
+    const WAIT: Duration = Duration::from_millis(20);
+
+    let client = C::init(addr.into(), config)
+        .await
+        .expect("Failed to create a client");
+    for _ in 0..ATTEMPTS {
+        if client.ping().await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(WAIT).await;
+    }
+
+    panic!("Server on {} didn't start", addr);
+}
+
+/// Wait until the given address accepts TCP connections
+pub async fn wait_for_listener(addr: SocketAddr) {
+    const ATTEMPTS: usize = 100;
+    const WAIT: Duration = Duration::from_millis(20);
+
+    for _ in 0..ATTEMPTS {
+        if tokio::net::TcpStream::connect(addr).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(WAIT).await;
+    }
+
+    panic!("Server on {} didn't start", addr);
+}
+
+/// Generate a test for each check of the [`ConformanceSuite`]
+///
+/// The calling crate needs `tokio` with the `macros` and `rt-multi-thread` features.
+///
+/// # Arguments
+///
+/// * `$client` - The client type under test
+/// * `$factory` - Expression creating the [`ServerFactory`] for the transport
+/// * `$config` - Optional expression creating the [`ClientConfig`] used by the clients
+///
+/// With `tls` before the factory, the suite uses TLS, see [`ConformanceSuite::with_tls`].
+/// The factory is then a closure, creating the [`ServerFactory`] from the TLS config.
+#[macro_export]
+macro_rules! client_conformance_tests {
+    ($client:ty, tls $factory:expr) => {
+        $crate::client_conformance_tests!(
+            @all $crate::testing::ConformanceSuite::<$client, _>::with_tls($factory)
+        );
+    };
+    ($client:ty, $factory:expr) => {
+        $crate::client_conformance_tests!(
+            $client,
+            $factory,
+            $crate::client::ClientConfig::default()
+        );
+    };
+    ($client:ty, $factory:expr, $config:expr) => {
+        $crate::client_conformance_tests!(
+            @all $crate::testing::ConformanceSuite::<$client, _>::with_config($factory, $config)
+        );
+    };
+    (@all $suite:expr) => {
+        $crate::client_conformance_tests!(
+            @checks $suite,
+            ping,
+            find_successor,
+            next_hop,
+            lookup,
+            successor,
+            successor_list,
+            predecessor,
+            notify,
+            host_name,
+            unauthenticated_notify,
+            invalid_notify,
+            timeout,
+            connection_failed
+        );
+    };
+    (@checks $suite:expr, $($check:ident),+) => {
+        $(
+            #[tokio::test(flavor = "multi_thread")]
+            async fn $check() {
+                $suite.$check().await;
+            }
+        )+
+    };
+}
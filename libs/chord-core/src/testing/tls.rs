@@ -0,0 +1,130 @@
+//! Self-signed certificates for testing the TLS support of the transports
+
+use std::net::SocketAddr;
+use std::path::Path;
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, IsCa};
+use tempfile::TempDir;
+
+use crate::client::{ClientConfig, TlsConfig, TlsIdentity};
+use crate::Client;
+
+/// CA and certificates signed by it, written to a temporary directory
+///
+/// All the certificates are issued for `localhost`. The directory is removed when the fixture
+/// is dropped.
+pub struct TlsFixture {
+    dir: TempDir,
+}
+
+impl TlsFixture {
+    /// Generate the certificates
+    pub fn generate() -> Self {
+        let dir = tempfile::Builder::new()
+            .prefix("chord-tls-")
+            .tempdir()
+            .expect("Failed to create the certificates directory");
+
+        let ca = Self::certificate("chord test CA", true);
+        write(dir.path(), "ca.pem", ca.serialize_pem().unwrap());
+
+        let rogue_ca = Self::certificate("rogue CA", true);
+        for (name, signer) in [("node", &ca), ("client", &ca), ("rogue", &rogue_ca)] {
+            let cert = Self::certificate(name, false);
+            write(
+                dir.path(),
+                &format!("{}.pem", name),
+                cert.serialize_pem_with_signer(signer).unwrap(),
+            );
+            write(
+                dir.path(),
+                &format!("{}.key", name),
+                cert.serialize_private_key_pem(),
+            );
+        }
+
+        Self { dir }
+    }
+
+    fn certificate(name: &str, is_ca: bool) -> Certificate {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, name);
+        params.distinguished_name = dn;
+        if is_ca {
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        }
+
+        Certificate::from_params(params).expect("Failed to generate a certificate")
+    }
+
+    fn identity(&self, name: &str) -> TlsIdentity {
+        TlsIdentity {
+            cert: self.dir.path().join(format!("{}.pem", name)),
+            key: self.dir.path().join(format!("{}.key", name)),
+        }
+    }
+
+    /// Config of a node, requiring client certificates when `client_auth` is set
+    pub fn node_config(&self, client_auth: bool) -> TlsConfig {
+        TlsConfig {
+            ca_cert: self.dir.path().join("ca.pem"),
+            identity: Some(self.identity("node")),
+            client_auth,
+            server_name: "localhost".to_string(),
+        }
+    }
+
+    /// Config of a node, which presents a certificate signed by an unknown CA
+    pub fn rogue_node_config(&self) -> TlsConfig {
+        TlsConfig {
+            identity: Some(self.identity("rogue")),
+            ..self.node_config(false)
+        }
+    }
+
+    /// Config of a client, which presents a certificate signed by the CA
+    pub fn client_config(&self) -> ClientConfig {
+        ClientConfig::with_tls(Some(TlsConfig {
+            identity: Some(self.identity("client")),
+            ..self.node_config(false)
+        }))
+    }
+
+    /// Config of a client without a certificate
+    pub fn anonymous_client_config(&self) -> ClientConfig {
+        ClientConfig::with_tls(Some(TlsConfig {
+            identity: None,
+            ..self.node_config(false)
+        }))
+    }
+
+    /// Config of a client, which presents a certificate signed by an unknown CA
+    pub fn rogue_client_config(&self) -> ClientConfig {
+        ClientConfig::with_tls(Some(TlsConfig {
+            identity: Some(self.identity("rogue")),
+            ..self.node_config(false)
+        }))
+    }
+}
+
+/// Assert that a client with the given config can't talk to the node on `addr`
+///
+/// # Arguments
+///
+/// * `addr` - The address of the node
+/// * `config` - The config of the client
+pub async fn assert_rejected<C: Client>(addr: SocketAddr, config: ClientConfig) {
+    // A client which can't even be created is rejected as well
+    if let Ok(client) = C::init(addr.into(), config.clone()).await {
+        assert!(
+            client.ping().await.is_err(),
+            "client with {:?} wasn't rejected",
+            config
+        );
+    }
+}
+
+fn write(dir: &Path, name: &str, content: String) {
+    std::fs::write(dir.join(name), content).expect("Failed to write a certificate");
+}
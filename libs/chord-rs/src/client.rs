@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+    time::Duration,
+};
+
+use chord_capnp::client::ChordCapnpClient;
+use chord_grpc::client::ChordGrpcClient;
+use chord_rs_core::{
+    client::{ClientConfig, ClientError},
+    lookup::NextHop,
+    Address, Client, Node, NodeId,
+};
+use error_stack::Result;
+
+use crate::Transport;
+
+/// Resolves the ids to the nodes which own them, talking to each node with the transport it speaks
+pub type Resolver = chord_rs_core::lookup::Resolver<PeerClient>;
+
+impl Transport {
+    /// The transport which is not `self`
+    pub fn other(self) -> Self {
+        match self {
+            Transport::Capnp => Transport::Grpc,
+            Transport::Grpc => Transport::Capnp,
+        }
+    }
+}
+
+/// Records which transport each peer speaks.
+///
+/// Peers which are not in the book yet are probed with both transports when
+/// the first client for them is created, starting with the default transport.
+#[derive(Debug)]
+pub struct AddressBook {
+    default: RwLock<Transport>,
+    peers: RwLock<HashMap<Address, Transport>>,
+}
+
+impl AddressBook {
+    /// Get the address book shared by all [`PeerClient`]s of the process
+    pub fn global() -> &'static AddressBook {
+        static BOOK: OnceLock<AddressBook> = OnceLock::new();
+        BOOK.get_or_init(|| AddressBook {
+            default: RwLock::new(Transport::Capnp),
+            peers: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Set the transport which is tried first for unknown peers
+    pub fn set_default(&self, transport: Transport) {
+        *self.default.write().unwrap() = transport;
+    }
+
+    /// Get the transport which is tried first for unknown peers
+    pub fn default_transport(&self) -> Transport {
+        *self.default.read().unwrap()
+    }
+
+    /// Record the transport spoken by a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the peer
+    /// * `transport` - The transport the peer speaks on that address
+    pub fn insert(&self, addr: Address, transport: Transport) {
+        self.peers.write().unwrap().insert(addr, transport);
+    }
+
+    /// Get the transport spoken by a peer, if it's known
+    pub fn get(&self, addr: &Address) -> Option<Transport> {
+        self.peers.read().unwrap().get(addr).copied()
+    }
+}
+
+/// Client which talks to each peer using the transport recorded in the [`AddressBook`]
+///
+/// It allows a node to be a part of a ring, where nodes use different transports,
+/// e.g. when migrating the ring from one transport to the other.
+#[derive(Clone)]
+pub enum PeerClient {
+    Capnp(ChordCapnpClient),
+    Grpc(ChordGrpcClient),
+}
+
+macro_rules! dispatch {
+    ($self:ident, $client:ident => $call:expr) => {
+        match $self {
+            PeerClient::Capnp($client) => $call,
+            PeerClient::Grpc($client) => $call,
+        }
+    };
+}
+
+impl PeerClient {
+    const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Create a client for the given transport
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The node address to connect to
+    /// * `transport` - The transport the node speaks
+    /// * `config` - The client configuration
+    pub async fn connect(
+        addr: Address,
+        transport: Transport,
+        config: ClientConfig,
+    ) -> Result<Self, ClientError> {
+        Ok(match transport {
+            Transport::Capnp => PeerClient::Capnp(ChordCapnpClient::init(addr, config).await?),
+            Transport::Grpc => PeerClient::Grpc(ChordGrpcClient::init(addr, config).await?),
+        })
+    }
+
+    pub fn transport(&self) -> Transport {
+        match self {
+            PeerClient::Capnp(_) => Transport::Capnp,
+            PeerClient::Grpc(_) => Transport::Grpc,
+        }
+    }
+
+    /// Find out which transport the peer speaks by pinging it with each of them.
+    async fn probe(addr: &Address, preferred: Transport, config: &ClientConfig) -> Option<Self> {
+        for transport in [preferred, preferred.other()] {
+            let client = match Self::connect(addr.clone(), transport, config.clone()).await {
+                Ok(client) => client,
+                Err(report) => {
+                    log::debug!("Failed to create {:?} client: {:?}", transport, report);
+                    continue;
+                }
+            };
+            if let Ok(Ok(())) = tokio::time::timeout(Self::PROBE_TIMEOUT, client.ping()).await {
+                return Some(client);
+            }
+            log::debug!("Peer {} does not respond to {:?}", addr, transport);
+        }
+
+        None
+    }
+}
+
+#[async_trait::async_trait]
+impl Client for PeerClient {
+    async fn init(addr: Address, config: ClientConfig) -> Result<Self, ClientError> {
+        let book = AddressBook::global();
+        if let Some(transport) = book.get(&addr) {
+            return Self::connect(addr, transport, config).await;
+        }
+
+        let preferred = book.default_transport();
+        match Self::probe(&addr, preferred, &config).await {
+            Some(client) => {
+                log::info!("Peer {} speaks {:?}", addr, client.transport());
+                book.insert(addr.clone(), client.transport());
+                Ok(client)
+            }
+            None => {
+                log::warn!(
+                    "Failed to detect the transport of {}, using {:?}",
+                    addr,
+                    preferred
+                );
+                Self::connect(addr, preferred, config).await
+            }
+        }
+    }
+
+    async fn find_successor(&self, id: NodeId) -> Result<Node, ClientError> {
+        dispatch!(self, client => client.find_successor(id).await)
+    }
+
+    async fn next_hop(&self, id: NodeId) -> Result<NextHop, ClientError> {
+        dispatch!(self, client => client.next_hop(id).await)
+    }
+
+    async fn successor(&self) -> Result<Node, ClientError> {
+        dispatch!(self, client => client.successor().await)
+    }
+
+    async fn successor_list(&self) -> Result<Vec<Node>, ClientError> {
+        dispatch!(self, client => client.successor_list().await)
+    }
+
+    async fn predecessor(&self) -> Result<Option<Node>, ClientError> {
+        dispatch!(self, client => client.predecessor().await)
+    }
+
+    async fn notify(&self, predecessor: Node) -> Result<(), ClientError> {
+        dispatch!(self, client => client.notify(predecessor).await)
+    }
+
+    async fn ping(&self) -> Result<(), ClientError> {
+        dispatch!(self, client => client.ping().await)
+    }
+}
@@ -1,28 +1,176 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+pub use chord_rs_core::auth::ClusterSecret;
+use chord_rs_core::client::ClientConfig;
+pub use chord_rs_core::client::{Timeouts, TlsConfig, TlsIdentity};
+pub use chord_rs_core::discovery::DiscoveryConfig;
+pub use chord_rs_core::lookup::CacheConfig;
+pub use chord_rs_core::server::Seed;
+use chord_rs_core::ServiceConfig;
+pub use chord_rs_core::{Address, FixFingers, NodeId};
+use error_stack::Result;
+use thiserror::Error;
 
 #[cfg(all(feature = "capnp", feature = "grpc"))]
-compile_error!("feature \"capnp\" and feature \"grpc\" cannot be enabled at the same time");
+pub mod client;
 
-use std::net::SocketAddr;
+#[cfg(all(feature = "capnp", feature = "grpc"))]
+use dual::Server as DualServer;
+
+#[cfg(feature = "capnp")]
+use capnp::Server as CapnpServer;
 
 #[cfg(feature = "grpc")]
-pub use grpc::Server;
+use grpc::Server as GrpcServer;
 
-#[cfg(feature = "capnp")]
-pub use capnp::Server;
+/// Transport used for the communication between nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    #[cfg(feature = "capnp")]
+    Capnp,
+    #[cfg(feature = "grpc")]
+    Grpc,
+}
 
 pub struct Config {
     pub addr: SocketAddr,
-    pub ring: Option<SocketAddr>,
+    /// Nodes of the ring to join through, tried in turn. The node starts a new ring when it's empty
+    pub ring: Vec<Seed>,
+    pub transport: Transport,
+    /// Address to serve the other transport on, next to `transport` on `addr`.
+    /// Used to migrate a ring from one transport to the other.
+    pub secondary_addr: Option<SocketAddr>,
+    /// TLS configuration used for both the incoming and outgoing connections,
+    /// plaintext TCP is used when it's not set
+    pub tls: Option<TlsConfig>,
+    /// Secret shared by the nodes of the ring, notify calls which aren't signed with it
+    /// are rejected. Membership isn't authenticated when it's not set.
+    pub cluster_secret: Option<ClusterSecret>,
 
     pub max_connections: usize,
+    /// Number of threads serving the capnp connections
+    pub workers: usize,
+    /// Number of capnp connections waiting for a slot when `max_connections` are open
+    pub accept_queue: usize,
+    /// How long a queued capnp connection waits for a slot before it's rejected as overloaded
+    pub accept_timeout: Duration,
+    /// Timeouts of the calls made to other nodes
+    pub timeouts: Timeouts,
+    /// Strategy used to keep the finger table up to date
+    pub fix_fingers: FixFingers,
+    /// Limits of the cache of the lookup results
+    pub cache: CacheConfig,
+    /// Multicast discovery of the other nodes, disabled when it's not set
+    pub discovery: Option<DiscoveryConfig>,
+    /// Address other nodes reach the node on, the node identifies itself with it.
+    /// The address the node listens on is advertised when it's not set.
+    pub advertise: Option<SocketAddr>,
+    /// Host name other nodes reach the node by, with the port of the advertised address.
+    /// The node is reached by the advertised address when it's not set.
+    pub host: Option<String>,
+    /// Id of the node, derived from the host name or the advertised address when it's not set
+    pub node_id: Option<NodeId>,
+}
+
+impl Config {
+    /// Get the config of the clients the node uses to talk to other nodes
+    pub(crate) fn client_config(&self) -> ClientConfig {
+        ClientConfig {
+            timeouts: self.timeouts.clone(),
+            ..ClientConfig::with_tls(self.tls.clone()).secret(self.cluster_secret.clone())
+        }
+    }
+
+    /// Get the config of the node service
+    pub(crate) fn service_config(&self) -> ServiceConfig {
+        ServiceConfig::from(self.client_config())
+            .fix_fingers(self.fix_fingers)
+            .cache(self.cache.clone())
+            .discovery(self.discovery.clone())
+            .advertise(self.advertise)
+            .host(self.host.clone())
+            .id(self.node_id)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("Failed to load the TLS config")]
+    Tls,
+    #[error("Failed to join the ring")]
+    JoinRing,
+    #[error("Server stopped with an error")]
+    Serve,
+}
+
+/// A chord node server, using the transport selected in the [`Config`]
+pub enum Server {
+    #[cfg(feature = "capnp")]
+    Capnp(Box<CapnpServer>),
+    #[cfg(feature = "grpc")]
+    Grpc(Box<GrpcServer>),
+    #[cfg(all(feature = "capnp", feature = "grpc"))]
+    Dual(Box<DualServer>),
+}
+
+impl Server {
+    /// Create a new server and join the ring
+    ///
+    /// # Errors
+    ///
+    /// Fails when the node can't join the ring, i.e. its id is used by another node,
+    /// or the TLS config of a gRPC server can't be loaded
+    pub async fn new(addr: SocketAddr, config: impl Into<Config>) -> Result<Server, ServerError> {
+        let config: Config = config.into();
+        log::info!("Starting {:?} server", config.transport);
+        if config.advertise.is_none() && config.host.is_none() && addr.ip().is_unspecified() {
+            log::warn!(
+                "Advertising unspecified address {}, other nodes can't reach it, set the advertised address",
+                addr
+            );
+        }
+
+        if let Some(secondary_addr) = config.secondary_addr {
+            #[cfg(all(feature = "capnp", feature = "grpc"))]
+            return Ok(Server::Dual(Box::new(
+                DualServer::new(addr, secondary_addr, config).await?,
+            )));
+
+            #[cfg(not(all(feature = "capnp", feature = "grpc")))]
+            log::warn!(
+                "Ignoring secondary address {}, both capnp and grpc features are required",
+                secondary_addr
+            );
+        }
+
+        Ok(match config.transport {
+            #[cfg(feature = "capnp")]
+            Transport::Capnp => Server::Capnp(Box::new(CapnpServer::new(addr, config).await?)),
+            #[cfg(feature = "grpc")]
+            Transport::Grpc => Server::Grpc(Box::new(GrpcServer::new(addr, config).await?)),
+        })
+    }
+
+    pub async fn run(self) -> Result<(), ServerError> {
+        match self {
+            #[cfg(feature = "capnp")]
+            Server::Capnp(server) => server.run().await,
+            #[cfg(feature = "grpc")]
+            Server::Grpc(server) => server.run().await,
+            #[cfg(all(feature = "capnp", feature = "grpc"))]
+            Server::Dual(server) => server.run().await,
+        }
+    }
 }
 
 #[cfg(feature = "capnp")]
 mod capnp {
     use std::net::SocketAddr;
 
-    use crate::Config;
+    use crate::{Config, ServerError};
     use chord_capnp::Server as CapnpServer;
+    use error_stack::{Result, ResultExt};
 
     pub struct Server {
         server: CapnpServer,
@@ -30,30 +178,41 @@ mod capnp {
     }
 
     impl Server {
-        pub async fn new(addr: SocketAddr, config: impl Into<Config>) -> Server {
+        pub async fn new(
+            addr: SocketAddr,
+            config: impl Into<Config>,
+        ) -> Result<Server, ServerError> {
             let config: Config = config.into();
-            let chord = CapnpServer::new(addr, config.ring).await;
+            let chord = CapnpServer::new(addr, config.ring.clone(), config.service_config())
+                .await
+                .change_context(ServerError::JoinRing)?
+                .workers(config.workers)
+                .accept_queue(config.accept_queue, config.accept_timeout);
 
-            Server {
+            Ok(Server {
                 server: chord,
-                config
-            }
+                config,
+            })
         }
 
-        pub async fn run(self) {
-            self.server.run(self.config.max_connections).await;
+        pub async fn run(self) -> Result<(), ServerError> {
+            self.server
+                .run(self.config.max_connections)
+                .await
+                .change_context(ServerError::Serve)
         }
     }
 }
 
 #[cfg(feature = "grpc")]
 mod grpc {
-    use std::net::SocketAddr;
     use chord_grpc::server::ChordNodeServer;
-    use chord_grpc::server::Server as GrpcServer;
     use chord_grpc::server::ChordService;
+    use chord_grpc::server::Server as GrpcServer;
+    use error_stack::{IntoReport, Result, ResultExt};
+    use std::net::SocketAddr;
 
-    use crate::Config;
+    use crate::{Config, ServerError, TlsConfig};
 
     pub struct Server {
         addr: SocketAddr,
@@ -61,24 +220,145 @@ mod grpc {
     }
 
     impl Server {
-        pub async fn new(addr: SocketAddr, config: impl Into<Config>) -> Server {
+        pub async fn new(
+            addr: SocketAddr,
+            config: impl Into<Config>,
+        ) -> Result<Server, ServerError> {
             let config: Config = config.into();
-            let chord = ChordService::new(addr, config.ring).await;
-    
-            let router = GrpcServer::builder()
-                .add_service(ChordNodeServer::new(chord));
-    
-            Server {
-                addr,
-                router
+            // The TLS config is loaded before the node becomes a member of the ring
+            let mut builder = builder(config.tls.as_ref())?;
+            let chord = ChordService::new(addr, config.ring.clone(), config.service_config())
+                .await
+                .change_context(ServerError::JoinRing)?;
+
+            let router = builder.add_service(ChordNodeServer::new(chord));
+
+            Ok(Server { addr, router })
+        }
+
+        pub async fn run(self) -> Result<(), ServerError> {
+            self.router
+                .serve(self.addr)
+                .await
+                .into_report()
+                .change_context(ServerError::Serve)?;
+            log::info!("Server stopped");
+            Ok(())
+        }
+    }
+
+    /// Create a server builder, using TLS when it's configured
+    ///
+    /// Fails when the TLS files can't be loaded or don't make a valid config.
+    pub(crate) fn builder(tls: Option<&TlsConfig>) -> Result<GrpcServer, ServerError> {
+        let builder = GrpcServer::builder();
+        match tls {
+            Some(tls) => {
+                let tls = chord_grpc::tls::server_config(tls).change_context(ServerError::Tls)?;
+                builder
+                    .tls_config(tls)
+                    .into_report()
+                    .change_context(ServerError::Tls)
             }
+            None => Ok(builder),
         }
-    
-        pub async fn run(self) {
-            match self.router.serve(self.addr).await {
-                Ok(_) => log::info!("Server stopped"),
-                Err(e) => log::error!("Server error: {}", e),
+    }
+}
+
+#[cfg(all(feature = "capnp", feature = "grpc"))]
+mod dual {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use chord_capnp::Server as CapnpServer;
+    use chord_grpc::server::ChordNodeServer;
+    use chord_grpc::server::ChordService;
+    use chord_rs_core::NodeService;
+    use error_stack::{IntoReport, Result, ResultExt};
+
+    use crate::client::{AddressBook, PeerClient};
+    use crate::{Config, ServerError, Transport};
+
+    /// Server exposing the same node over both capnp and gRPC
+    pub struct Server {
+        capnp: CapnpServer<PeerClient>,
+        grpc_addr: SocketAddr,
+        router: tonic::transport::server::Router,
+        max_connections: usize,
+    }
+
+    impl Server {
+        /// Create a new server
+        ///
+        /// # Arguments
+        ///
+        /// * `addr` - The address of the node, it serves `config.transport`
+        /// * `secondary_addr` - The address serving the other transport
+        /// * `config` - The server configuration
+        pub async fn new(
+            addr: SocketAddr,
+            secondary_addr: SocketAddr,
+            config: Config,
+        ) -> Result<Server, ServerError> {
+            const REPLICATION_FACTOR: usize = 3; // TODO: make this configurable
+            AddressBook::global().set_default(config.transport);
+            // The TLS config is loaded before the node becomes a member of the ring
+            let mut builder = crate::grpc::builder(config.tls.as_ref())?;
+
+            let node_service = Arc::new(NodeService::with_config(
+                addr,
+                REPLICATION_FACTOR,
+                config.service_config(),
+            ));
+            chord_rs_core::server::join_ring(node_service.clone(), config.ring.clone())
+                .await
+                .change_context(ServerError::JoinRing)?;
+            if let Some(discovery) = config.discovery.clone() {
+                chord_rs_core::discovery::spawn(node_service.clone(), discovery);
             }
-        }    
+            chord_rs_core::server::background_tasks(node_service.clone());
+
+            let (capnp_addr, grpc_addr) = match config.transport {
+                Transport::Capnp => (addr, secondary_addr),
+                Transport::Grpc => (secondary_addr, addr),
+            };
+            log::info!("Serving capnp on {} and gRPC on {}", capnp_addr, grpc_addr);
+
+            let capnp =
+                CapnpServer::with_service(capnp_addr, node_service.clone(), config.tls.clone())
+                    .workers(config.workers)
+                    .accept_queue(config.accept_queue, config.accept_timeout);
+            let router = builder.add_service(ChordNodeServer::new(ChordService::with_service(
+                node_service,
+            )));
+
+            Ok(Server {
+                capnp,
+                grpc_addr,
+                router,
+                max_connections: config.max_connections,
+            })
+        }
+
+        /// Serve both transports, stopping when either of them fails
+        pub async fn run(self) -> Result<(), ServerError> {
+            let capnp = async {
+                self.capnp
+                    .run(self.max_connections)
+                    .await
+                    .change_context(ServerError::Serve)
+            };
+            let grpc = async {
+                self.router
+                    .serve(self.grpc_addr)
+                    .await
+                    .into_report()
+                    .change_context(ServerError::Serve)?;
+                log::info!("gRPC server stopped");
+                Ok(())
+            };
+
+            tokio::try_join!(capnp, grpc).map(|_| ())
+        }
     }
 }
@@ -1,157 +1,298 @@
+use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
-use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use crate::server::chord_proto::chord_node_client::ChordNodeClient;
 use crate::server::chord_proto::{
-    self, FindSuccessorRequest, GetPredecessorRequest, NotifyRequest,
+    self, FindSuccessorRequest, GetPredecessorRequest, GetSuccessorListRequest, NextHopRequest,
+    NotifyRequest,
 };
-use chord_rs_core::client::ClientError;
-use chord_rs_core::{Client, Node, NodeId};
+use crate::tls::{self, TlsError};
+use chord_rs_core::auth::ClusterSecret;
+use chord_rs_core::client::{ClientConfig, ClientError, Timeouts};
+use chord_rs_core::error::ServiceError;
+use chord_rs_core::lookup::{NextHop, PrecedingFinger};
+use chord_rs_core::{deadline, Address, Client, Node, NodeId};
 use error_stack::{IntoReport, Report, Result, ResultExt};
-use tonic::async_trait;
 use tonic::transport::{Channel, Endpoint};
+use tonic::{async_trait, Code, Status};
 
 #[derive(Debug)]
 pub struct ChordGrpcClient {
-    // pub(crate) endpoint: Endpoint,
-    pub(crate) client: ClientGuard,
-}
-
-#[derive(Debug, Clone)]
-pub(crate) struct ClientGuard {
-    client: Arc<Mutex<Option<ChordNodeClient<Channel>>>>,
-}
-
-impl ClientGuard {
-    fn new() -> Self {
-        Self {
-            client: Arc::new(Mutex::new(None)),
-        }
-    }
+    pub(crate) client: ChordNodeClient<Channel>,
+    /// Secret used to sign the notify calls
+    pub(crate) secret: Option<ClusterSecret>,
+    pub(crate) timeouts: Timeouts,
 }
 
 #[async_trait]
 impl Client for ChordGrpcClient {
-    async fn init(addr: SocketAddr) -> Self {
+    async fn init(addr: Address, config: ClientConfig) -> Result<Self, ClientError> {
         log::debug!("Initializing client for {}", addr);
-        let endpoint = Endpoint::from_shared(format!("http://{}", addr)).unwrap();
-        let client_guard = ClientGuard::new();
-        let client_guard_clone = client_guard.clone();
-
-        let client = ChordNodeClient::connect(endpoint.clone()).await;
-        if let Err(err) = &client {
-            log::error!("Failed to initialize client: {:?}", err);
-        } else {
-            log::debug!("Client initialized");
-            client_guard_clone
-                .client
-                .lock()
-                .unwrap()
-                .replace(client.unwrap());
-        }
-
-        ChordGrpcClient {
-            client: client_guard,
-        }
+        let endpoint = Self::endpoint(&addr, &config)?;
+
+        // The channel connects on the first request and reconnects when the connection
+        // is lost, so a node which is down is reported as `ConnectionFailed` by the calls.
+        let client = ChordNodeClient::new(endpoint.connect_lazy());
+        log::debug!("Client initialized");
+
+        Ok(ChordGrpcClient {
+            client,
+            secret: config.secret,
+            timeouts: config.timeouts,
+        })
     }
 
     async fn find_successor(&self, id: NodeId) -> Result<Node, ClientError> {
-        let mut client = self.client()?;
-
-        let request = tonic::Request::new(FindSuccessorRequest { id: id.into() });
-        let response = client
-            .find_successor(request)
-            .await
-            .into_report()
-            .change_context(ClientError::Unexpected)?;
-        // if let Err(err) = response {
-        //     log::warn!("Failed to find successor: {:?}", err);
-        //     return Err(ClientError::Unexpected(err.to_string()));
-        // }
-        let response = response.into_inner();
+        let mut client = self.client.clone();
 
-        let node = response.node.unwrap();
-        let node: Node = node.try_into().unwrap();
+        let (request, timeout) = Self::request(
+            FindSuccessorRequest { id: id.into() },
+            self.timeouts.find_successor,
+        );
+        let response = Self::call(
+            timeout,
+            ClientError::FindSuccessorFailed,
+            client.find_successor(request),
+        )
+        .await?;
+
+        Self::parse_node(response.node, ClientError::FindSuccessorFailed)
+    }
 
-        Ok(node)
+    async fn next_hop(&self, id: NodeId) -> Result<NextHop, ClientError> {
+        let mut client = self.client.clone();
+
+        let (request, timeout) =
+            Self::request(NextHopRequest { id: id.into() }, self.timeouts.next_hop);
+        let response = Self::call(
+            timeout,
+            ClientError::NextHopFailed,
+            client.next_hop(request),
+        )
+        .await?;
+
+        let node = Self::parse_node(response.node, ClientError::NextHopFailed)?;
+        let successor = Self::parse_node(response.successor, ClientError::NextHopFailed)?;
+        let finger = match response.finger {
+            Some(finger) => Some(PrecedingFinger {
+                index: u8::try_from(finger.index)
+                    .map_err(|_| {
+                        ClientError::InvalidRequest(format!(
+                            "Invalid finger index {}",
+                            finger.index
+                        ))
+                    })
+                    .into_report()
+                    .attach_printable(ClientError::NextHopFailed.to_string())?,
+                node: Self::parse_node(finger.node, ClientError::NextHopFailed)?,
+            }),
+            None => None,
+        };
+
+        Ok(NextHop {
+            node,
+            successor,
+            finger,
+        })
     }
 
     async fn successor(&self) -> Result<Node, ClientError> {
-        let mut client = self.client()?;
-
-        let request = tonic::Request::new(chord_proto::GetSuccessorRequest {});
-
-        let response = client.get_successor(request).await.unwrap().into_inner();
-
-        if let Some(node) = response.node {
-            let node: Node = node.try_into().unwrap();
-            Ok(node)
-        } else {
-            Err(Report::new(ClientError::Unexpected).attach_printable("No successor found"))
-        }
+        let mut client = self.client.clone();
+
+        let (request, timeout) =
+            Self::request(chord_proto::GetSuccessorRequest {}, self.timeouts.successor);
+        let response = Self::call(
+            timeout,
+            ClientError::GetSuccessorFailed,
+            client.get_successor(request),
+        )
+        .await?;
+
+        Self::parse_node(response.node, ClientError::GetSuccessorFailed)
     }
 
     async fn successor_list(&self) -> Result<Vec<Node>, ClientError> {
-        todo!("successor_list")
+        let mut client = self.client.clone();
+
+        let (request, timeout) =
+            Self::request(GetSuccessorListRequest {}, self.timeouts.successor_list);
+        let response = Self::call(
+            timeout,
+            ClientError::GetSuccessorListFailed,
+            client.get_successor_list(request),
+        )
+        .await?;
+
+        response
+            .nodes
+            .into_iter()
+            .map(|node| Self::parse_node(Some(node), ClientError::GetSuccessorListFailed))
+            .collect()
     }
 
     async fn predecessor(&self) -> Result<Option<Node>, ClientError> {
-        let mut client = self.client()?;
-
-        let request = tonic::Request::new(GetPredecessorRequest {});
-
-        let response = client.get_predecessor(request).await.unwrap().into_inner();
-
-        if let Some(node) = response.node {
-            let node: Node = node.try_into().unwrap();
-            return Ok(Some(node));
+        let mut client = self.client.clone();
+
+        let (request, timeout) = Self::request(GetPredecessorRequest {}, self.timeouts.predecessor);
+        let response = Self::call(
+            timeout,
+            ClientError::GetPredecessorFailed,
+            client.get_predecessor(request),
+        )
+        .await?;
+
+        match response.node {
+            Some(node) => {
+                let node = Self::parse_node(Some(node), ClientError::GetPredecessorFailed)?;
+                Ok(Some(node))
+            }
+            None => Ok(None),
         }
-
-        Ok(None)
     }
 
     async fn notify(&self, predecessor: Node) -> Result<(), ClientError> {
-        let mut client = self.client()?;
-
-        let request = tonic::Request::new(NotifyRequest {
+        let mut client = self.client.clone();
+
+        let token = self
+            .secret
+            .as_ref()
+            .map(|secret| secret.sign(&predecessor))
+            .unwrap_or_default();
+        let message = NotifyRequest {
             node: Some(predecessor.into()),
-        });
-        client.notify(request).await.unwrap();
+            token,
+        };
+        let (request, timeout) = Self::request(message, self.timeouts.notify);
+        Self::call(timeout, ClientError::NotifyFailed, client.notify(request)).await?;
 
         Ok(())
     }
 
     async fn ping(&self) -> Result<(), ClientError> {
-        let mut client = self.client()?;
+        let mut client = self.client.clone();
 
-        let request = tonic::Request::new(chord_proto::PingRequest {});
-        client.ping(request).await.unwrap();
+        let (request, timeout) = Self::request(chord_proto::PingRequest {}, self.timeouts.ping);
+        Self::call(timeout, ClientError::PingFailed, client.ping(request)).await?;
 
         Ok(())
     }
 }
 
 impl ChordGrpcClient {
-    pub async fn new(addr: SocketAddr) -> Self {
-        Self::init(addr).await
+    pub async fn new(addr: SocketAddr) -> Result<Self, ClientError> {
+        Self::init(addr.into(), ClientConfig::default()).await
     }
 
-    pub fn client(&self) -> Result<ChordNodeClient<Channel>, ClientError> {
-        if let Some(client) = self.client.client.lock().unwrap().clone() {
-            Ok(client)
+    /// The host name of `addr` is resolved when the channel connects
+    ///
+    /// # Errors
+    ///
+    /// * `ClientError::ConnectionFailed` - The address isn't a valid URI authority
+    /// * `ClientError::InvalidConfig` - The TLS config can't be loaded
+    fn endpoint(addr: &Address, config: &ClientConfig) -> Result<Endpoint, ClientError> {
+        let scheme = if config.tls.is_some() {
+            "https"
         } else {
-            Err(Report::new(ClientError::NotInitialized))
+            "http"
+        };
+        let endpoint = Endpoint::from_shared(format!("{}://{}", scheme, addr))
+            .into_report()
+            .change_context_lazy(|| {
+                ClientError::ConnectionFailed(format!("Invalid address {}", addr))
+            })?;
+
+        match &config.tls {
+            Some(tls) => tls::client_config(tls)
+                .and_then(|tls| {
+                    endpoint
+                        .tls_config(tls)
+                        .into_report()
+                        .change_context(TlsError::InvalidConfig)
+                })
+                .change_context_lazy(|| {
+                    ClientError::InvalidConfig(format!("Failed to set up TLS for {}", addr))
+                }),
+            None => Ok(endpoint),
         }
     }
+
+    /// Create a request, which has to complete within the timeout capped by the current deadline
+    ///
+    /// The timeout is sent along, so the server runs the call within the same deadline.
+    fn request<T>(message: T, timeout: Duration) -> (tonic::Request<T>, Duration) {
+        let timeout = deadline::budget(timeout);
+        let mut request = tonic::Request::new(message);
+        request.set_timeout(timeout);
+        (request, timeout)
+    }
+
+    /// Wait for the response of a call, the call is cancelled when the timeout passes
+    async fn call<T>(
+        timeout: Duration,
+        ctx: ClientError,
+        call: impl Future<Output = std::result::Result<tonic::Response<T>, Status>>,
+    ) -> Result<T, ClientError> {
+        match tokio::time::timeout(timeout, call).await {
+            Ok(Ok(response)) => Ok(response.into_inner()),
+            Ok(Err(status)) => Err(Self::map_status(status, ctx)),
+            Err(_) => Err(Report::new(ClientError::Timeout(format!(
+                "No response in {:?}",
+                timeout
+            )))
+            .attach_printable(ctx.to_string())),
+        }
+    }
+
+    /// Map a gRPC status to a client error
+    ///
+    /// The current context describes the failure, so callers can tell a node which is down
+    /// from other failures. The failed operation is attached to the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The status returned by the server or by the channel
+    /// * `ctx` - The error describing the failed operation
+    fn map_status(status: Status, ctx: ClientError) -> Report<ClientError> {
+        log::debug!("gRPC error: {:?}", status);
+        let kind = match status.code() {
+            Code::Unavailable => ServiceError::Unavailable,
+            Code::InvalidArgument => ServiceError::InvalidArgument,
+            Code::Unauthenticated => ServiceError::Unauthenticated,
+            Code::ResourceExhausted => ServiceError::Overloaded,
+            Code::AlreadyExists => ServiceError::DuplicateId,
+            Code::FailedPrecondition => ServiceError::NotOwner,
+            // The channel cancels the calls which exceed the timeout sent with the request
+            Code::DeadlineExceeded | Code::Cancelled => ServiceError::Timeout,
+            _ => ServiceError::Unexpected,
+        };
+        let error = ClientError::from_service(kind, status.message().to_string());
+
+        Report::new(error)
+            .attach_printable(status)
+            .attach_printable(ctx.to_string())
+    }
+
+    fn parse_node(node: Option<chord_proto::Node>, ctx: ClientError) -> Result<Node, ClientError> {
+        let node = node
+            .ok_or_else(|| ClientError::InvalidRequest("Missing node in response".to_string()))
+            .into_report()
+            .attach_printable_lazy(|| ctx.to_string())?;
+
+        Node::try_from(node)
+            .map_err(|err| ClientError::InvalidRequest(err.to_string()))
+            .into_report()
+            .attach_printable(ctx.to_string())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct IpParseError {
     msg: String,
 }
 
 impl IpParseError {
-    fn new(msg: &str) -> Self {
+    pub(crate) fn new(msg: &str) -> Self {
         IpParseError {
             msg: msg.to_string(),
         }
@@ -164,6 +305,8 @@ impl std::fmt::Display for IpParseError {
     }
 }
 
+impl std::error::Error for IpParseError {}
+
 impl TryFrom<chord_proto::IpAddress> for IpAddr {
     type Error = IpParseError;
 
@@ -256,4 +399,74 @@ mod tests {
         assert!(invalid_ip.is_err());
         assert_eq!("Invalid IPv6 address", invalid_ip.err().unwrap().msg);
     }
+
+    #[test]
+    fn map_status_to_client_error() {
+        let report = ChordGrpcClient::map_status(
+            Status::unavailable("connection refused"),
+            ClientError::PingFailed,
+        );
+        assert!(matches!(
+            report.current_context(),
+            ClientError::ConnectionFailed(msg) if msg == "connection refused"
+        ));
+
+        let report = ChordGrpcClient::map_status(
+            Status::invalid_argument("Missing node"),
+            ClientError::NotifyFailed,
+        );
+        assert!(matches!(
+            report.current_context(),
+            ClientError::InvalidRequest(msg) if msg == "Missing node"
+        ));
+
+        let report = ChordGrpcClient::map_status(
+            Status::resource_exhausted("too many requests"),
+            ClientError::PingFailed,
+        );
+        assert!(matches!(
+            report.current_context(),
+            ClientError::Overloaded(msg) if msg == "too many requests"
+        ));
+
+        let report = ChordGrpcClient::map_status(
+            Status::deadline_exceeded("Deadline exceeded"),
+            ClientError::FindSuccessorFailed,
+        );
+        assert!(matches!(
+            report.current_context(),
+            ClientError::Timeout(msg) if msg == "Deadline exceeded"
+        ));
+
+        let report = ChordGrpcClient::map_status(
+            Status::cancelled("Timeout expired"),
+            ClientError::PingFailed,
+        );
+        assert!(matches!(
+            report.current_context(),
+            ClientError::Timeout(msg) if msg == "Timeout expired"
+        ));
+
+        let report = ChordGrpcClient::map_status(
+            Status::failed_precondition("Not the owner"),
+            ClientError::FindSuccessorFailed,
+        );
+        assert!(matches!(
+            report.current_context(),
+            ClientError::NotOwner(msg) if msg == "Not the owner"
+        ));
+
+        let report = ChordGrpcClient::map_status(
+            Status::already_exists("Duplicate node id"),
+            ClientError::FindSuccessorFailed,
+        );
+        assert!(matches!(
+            report.current_context(),
+            ClientError::DuplicateId(msg) if msg == "Duplicate node id"
+        ));
+
+        let report =
+            ChordGrpcClient::map_status(Status::internal("boom"), ClientError::NotifyFailed);
+        assert!(matches!(report.current_context(), ClientError::Unexpected));
+    }
 }
@@ -1,22 +1,44 @@
 use std::net::SocketAddr;
 
+use chord_rs_core::InvalidNode;
+use client::IpParseError;
 use server::chord_proto;
+use thiserror::Error;
 
 pub mod client;
 pub mod server;
+pub mod tls;
+
+/// A node received from another node which can't be used
+#[derive(Debug, Error)]
+pub enum NodeParseError {
+    #[error("Missing IP address")]
+    MissingIp,
+    #[error("{0}")]
+    Ip(#[from] IpParseError),
+    #[error("Invalid port {0}")]
+    Port(i32),
+    #[error(transparent)]
+    Invalid(#[from] InvalidNode),
+}
 
 impl TryFrom<chord_proto::Node> for chord_rs_core::Node {
-    type Error = std::net::AddrParseError;
+    type Error = NodeParseError;
 
     fn try_from(node: chord_proto::Node) -> Result<Self, Self::Error> {
         let id = node.id;
-        let ip = node.ip.unwrap();
-        let ip = ip.try_into().unwrap();
-        let port = node.port as u16;
+        let ip = node.ip.ok_or(NodeParseError::MissingIp)?;
+        let ip = ip.try_into()?;
+        let port = u16::try_from(node.port).map_err(|_| NodeParseError::Port(node.port))?;
 
         let addr = SocketAddr::new(ip, port);
 
-        Ok(chord_rs_core::Node::with_id(id, addr))
+        let host = Some(node.host).filter(|host| !host.is_empty());
+
+        let node = chord_rs_core::Node::with_id(id, addr).with_host(host);
+        node.validate()?;
+
+        Ok(node)
     }
 }
 
@@ -26,7 +48,130 @@ pub fn add(left: usize, right: usize) -> usize {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use chord_rs_core::client::TlsConfig;
+    use chord_rs_core::{Client, NodeService};
+
     use super::*;
+    use crate::client::ChordGrpcClient;
+    use crate::server::{ChordNodeServer, ChordService, Server};
+
+    fn serve(addr: SocketAddr, node: Arc<NodeService<ChordGrpcClient>>) {
+        serve_with_tls(addr, node, None);
+    }
+
+    fn serve_with_tls<C: Client + Clone + Sync + Send + 'static>(
+        addr: SocketAddr,
+        node: Arc<NodeService<C>>,
+        tls: Option<TlsConfig>,
+    ) {
+        let mut builder = Server::builder();
+        if let Some(tls) = tls {
+            builder = builder
+                .tls_config(crate::tls::server_config(&tls).unwrap())
+                .unwrap();
+        }
+
+        let service = ChordService::with_service(node);
+        tokio::spawn(
+            builder
+                .add_service(ChordNodeServer::new(service))
+                .serve(addr),
+        );
+    }
+
+    chord_rs_core::client_conformance_tests!(ChordGrpcClient, serve);
+
+    #[test]
+    fn test_parse_node() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 42001));
+        let node = chord_rs_core::Node::with_id(8, addr).with_host(Some("node-a".to_string()));
+        let proto = chord_proto::Node::from(node.clone());
+        assert_eq!(chord_rs_core::Node::try_from(proto.clone()).unwrap(), node);
+
+        let parse = |proto| chord_rs_core::Node::try_from(proto).unwrap_err();
+        let missing_ip = chord_proto::Node {
+            ip: None,
+            ..proto.clone()
+        };
+        assert!(matches!(parse(missing_ip), NodeParseError::MissingIp));
+        let port = chord_proto::Node {
+            port: 70000,
+            ..proto.clone()
+        };
+        assert!(matches!(parse(port), NodeParseError::Port(70000)));
+        let port = chord_proto::Node {
+            port: 0,
+            ..proto.clone()
+        };
+        assert!(matches!(parse(port), NodeParseError::Invalid(_)));
+        let host = chord_proto::Node {
+            host: "node a".to_string(),
+            ..proto
+        };
+        assert!(matches!(parse(host), NodeParseError::Invalid(_)));
+    }
+
+    mod dyn_client {
+        use chord_rs_core::client::{ClientConfig, DynClient, InitConnector};
+
+        use super::*;
+
+        fn serve(addr: SocketAddr, node: Arc<NodeService<DynClient>>) {
+            serve_with_tls(addr, node, None);
+        }
+
+        chord_rs_core::client_conformance_tests!(
+            DynClient,
+            serve,
+            ClientConfig::default()
+                .connector(Some(Arc::new(InitConnector::<ChordGrpcClient>::new())))
+        );
+    }
+
+    mod tls {
+        use chord_rs_core::client::ClientConfig;
+        use chord_rs_core::testing::tls::{assert_rejected, TlsFixture};
+        use chord_rs_core::testing::{free_addr, wait_for, wait_for_listener};
+
+        use super::*;
+
+        chord_rs_core::client_conformance_tests!(ChordGrpcClient, tls |tls: TlsConfig| {
+            move |addr, node| serve_with_tls(addr, node, Some(tls.clone()))
+        });
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn untrusted_clients_are_rejected() {
+            let fixture = TlsFixture::generate();
+            let addr = free_addr();
+            serve_with_tls(
+                addr,
+                Arc::new(NodeService::<ChordGrpcClient>::new(addr, 3)),
+                Some(fixture.node_config(true)),
+            );
+            wait_for::<ChordGrpcClient>(addr, fixture.client_config()).await;
+
+            assert_rejected::<ChordGrpcClient>(addr, ClientConfig::default()).await;
+            assert_rejected::<ChordGrpcClient>(addr, fixture.anonymous_client_config()).await;
+            assert_rejected::<ChordGrpcClient>(addr, fixture.rogue_client_config()).await;
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn untrusted_servers_are_rejected() {
+            let fixture = TlsFixture::generate();
+            let addr = free_addr();
+            let tls = fixture.rogue_node_config();
+            serve_with_tls(
+                addr,
+                Arc::new(NodeService::<ChordGrpcClient>::new(addr, 3)),
+                Some(tls),
+            );
+            wait_for_listener(addr).await;
+
+            assert_rejected::<ChordGrpcClient>(addr, fixture.client_config()).await;
+        }
+    }
 
     #[test]
     fn it_works() {
@@ -1,21 +1,28 @@
 use std::{
     net::{IpAddr, SocketAddr},
     sync::Arc,
+    time::Duration,
 };
 
 use chord_proto::chord_node_server::ChordNode;
 pub use chord_proto::chord_node_server::ChordNodeServer;
 use chord_proto::{PingRequest, PingResponse};
-use chord_rs_core::{Node, NodeService};
+use chord_rs_core::{
+    deadline, error::ServiceError, lookup::NextHop, server::Seed, Client, Node, NodeService,
+    ServiceConfig,
+};
 use error_stack::Report;
+use tokio::time::Instant;
+use tonic::metadata::MetadataMap;
 pub use tonic::transport::Server;
-use tonic::{Request, Response, Status};
+use tonic::{Code, Request, Response, Status};
 
 use crate::client::ChordGrpcClient;
 
 use self::chord_proto::{
     FindSuccessorRequest, FindSuccessorResponse, GetPredecessorRequest, GetPredecessorResponse,
-    GetSuccessorResponse, NotifyRequest, NotifyResponse,
+    GetSuccessorListRequest, GetSuccessorListResponse, GetSuccessorResponse, NextHopRequest,
+    NextHopResponse, NotifyRequest, NotifyResponse,
 };
 
 pub mod chord_proto {
@@ -27,6 +34,8 @@ pub mod chord_proto {
         fn clone(&self) -> Self {
             Self {
                 client: self.client.clone(),
+                secret: self.secret.clone(),
+                timeouts: self.timeouts.clone(),
             }
         }
     }
@@ -35,50 +44,121 @@ pub mod chord_proto {
     unsafe impl Send for ChordGrpcClient {}
 }
 
-#[derive(Debug, Clone)]
-pub struct ChordService {
-    node: Arc<NodeService<ChordGrpcClient>>,
+#[derive(Debug)]
+pub struct ChordService<C: Client = ChordGrpcClient> {
+    node: Arc<NodeService<C>>,
+}
+
+impl<C: Client> Clone for ChordService<C> {
+    fn clone(&self) -> Self {
+        Self {
+            node: self.node.clone(),
+        }
+    }
 }
 
 impl ChordService {
-    pub async fn new(addr: SocketAddr, ring: Option<SocketAddr>) -> Self {
+    /// Create a new service and join the ring
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the node
+    /// * `ring` - The nodes of the ring to join through, tried in turn.
+    ///   The node starts a new ring when there are none
+    /// * `config` - The config of the node, or just the config of the clients
+    ///   used to talk to other nodes
+    ///
+    /// # Errors
+    ///
+    /// Fails when the node can't join the ring, see [`chord_rs_core::server::join_ring`]
+    pub async fn new(
+        addr: SocketAddr,
+        ring: Vec<Seed>,
+        config: impl Into<ServiceConfig>,
+    ) -> Result<Self, Report<ServiceError>> {
         const REPLICATION_FACTOR: usize = 3; // TODO: make this configurable
-        let node_service = Arc::new(NodeService::new(addr, REPLICATION_FACTOR));
+        let config = config.into();
+        let discovery = config.discovery.clone();
+        let node_service = Arc::new(NodeService::with_config(addr, REPLICATION_FACTOR, config));
 
-        if let Some(ring) = ring {
-            const MAX_RETRIES: u32 = 5;
-            chord_rs_core::server::join_ring(node_service.clone(), ring, MAX_RETRIES).await;
+        chord_rs_core::server::join_ring(node_service.clone(), ring).await?;
+        if let Some(discovery) = discovery {
+            chord_rs_core::discovery::spawn(node_service.clone(), discovery);
         }
         chord_rs_core::server::background_tasks(node_service.clone());
 
-        Self { node: node_service }
+        Ok(Self { node: node_service })
     }
+}
 
-    fn map_error(error: Report<chord_rs_core::error::ServiceError>) -> Status {
+impl<C: Client> ChordService<C> {
+    /// Create a service for an already existing node service.
+    ///
+    /// Joining the ring and running the background tasks is up to the caller,
+    /// which allows the same node service to be exposed by multiple servers.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node service to expose
+    pub fn with_service(node: Arc<NodeService<C>>) -> Self {
+        Self { node }
+    }
+
+    /// Map a service error to the status code of its kind, which the client maps back
+    fn map_error(error: Report<ServiceError>) -> Status {
         let message = error.to_string();
-        match error.current_context() {
-            chord_rs_core::error::ServiceError::Unexpected => Status::internal(message),
-            chord_rs_core::error::ServiceError::ClientDisconnected => todo!(),
-        }
+        let code = match error.current_context() {
+            ServiceError::Unexpected => Code::Internal,
+            ServiceError::Unavailable => Code::Unavailable,
+            ServiceError::Unauthenticated => Code::Unauthenticated,
+            ServiceError::Timeout => Code::DeadlineExceeded,
+            ServiceError::DuplicateId => Code::AlreadyExists,
+            ServiceError::NotOwner => Code::FailedPrecondition,
+            ServiceError::Overloaded => Code::ResourceExhausted,
+            ServiceError::InvalidArgument => Code::InvalidArgument,
+        };
+
+        Status::new(code, message)
     }
 }
 
+/// Deadline of a request, sent by the client in the `grpc-timeout` header
+fn request_deadline(metadata: &MetadataMap) -> Option<Instant> {
+    let value = metadata.get("grpc-timeout")?.to_str().ok()?;
+    if value.is_empty() {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount.checked_mul(60 * 60)?),
+        "M" => Duration::from_secs(amount.checked_mul(60)?),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    };
+
+    Instant::now().checked_add(timeout)
+}
+
 pub enum JoinRingError {
     ClientError,
     ServiceError,
 }
 
-impl From<chord_rs_core::error::ServiceError> for JoinRingError {
-    fn from(error: chord_rs_core::error::ServiceError) -> Self {
+impl From<ServiceError> for JoinRingError {
+    fn from(error: ServiceError) -> Self {
         match error {
-            chord_rs_core::error::ServiceError::Unexpected => Self::ServiceError,
-            chord_rs_core::error::ServiceError::ClientDisconnected => todo!(),
+            ServiceError::Unexpected => Self::ServiceError,
+            _ => Self::ClientError,
         }
     }
 }
 
 #[tonic::async_trait]
-impl ChordNode for ChordService {
+impl<C: Client + Clone + Sync + Send + 'static> ChordNode for ChordService<C> {
     async fn ping(&self, _request: Request<PingRequest>) -> Result<Response<PingResponse>, Status> {
         let reply = chord_proto::PingResponse {};
 
@@ -89,15 +169,27 @@ impl ChordNode for ChordService {
         &self,
         request: Request<FindSuccessorRequest>,
     ) -> Result<Response<FindSuccessorResponse>, Status> {
-        let result = self
-            .node
-            .find_successor(request.get_ref().id.into())
-            .await
-            .map_err(Self::map_error)?;
+        let deadline = request_deadline(request.metadata());
+        let result = deadline::scope(
+            deadline,
+            self.node
+                .find_successor_uncached(request.get_ref().id.into()),
+        )
+        .await
+        .map_err(Self::map_error)?;
 
         Ok(Response::new(result.into()))
     }
 
+    async fn next_hop(
+        &self,
+        request: Request<NextHopRequest>,
+    ) -> Result<Response<NextHopResponse>, Status> {
+        let next_hop = self.node.next_hop(request.get_ref().id.into());
+
+        Ok(Response::new(next_hop.into()))
+    }
+
     async fn get_successor(
         &self,
         _request: Request<chord_proto::GetSuccessorRequest>,
@@ -107,6 +199,19 @@ impl ChordNode for ChordService {
         Ok(Response::new(result.into()))
     }
 
+    async fn get_successor_list(
+        &self,
+        _request: Request<GetSuccessorListRequest>,
+    ) -> Result<Response<GetSuccessorListResponse>, Status> {
+        let result = self
+            .node
+            .get_successor_list()
+            .await
+            .map_err(Self::map_error)?;
+
+        Ok(Response::new(result.into()))
+    }
+
     async fn get_predecessor(
         &self,
         _request: Request<GetPredecessorRequest>,
@@ -120,10 +225,14 @@ impl ChordNode for ChordService {
         &self,
         request: Request<NotifyRequest>,
     ) -> Result<Response<NotifyResponse>, Status> {
-        let node = request.get_ref().node.clone();
-        let node = Node::try_from(node.unwrap()).unwrap();
+        let request = request.into_inner();
+        let node = request
+            .node
+            .ok_or_else(|| Status::invalid_argument("Missing node"))?;
+        let node = Node::try_from(node).map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let token = (!request.token.is_empty()).then_some(request.token.as_slice());
 
-        self.node.notify(node);
+        self.node.notify(node, token).map_err(Self::map_error)?;
 
         Ok(Response::new(NotifyResponse {}))
     }
@@ -137,6 +246,19 @@ impl From<chord_rs_core::Node> for FindSuccessorResponse {
     }
 }
 
+impl From<NextHop> for NextHopResponse {
+    fn from(next_hop: NextHop) -> Self {
+        NextHopResponse {
+            node: Some(next_hop.node.into()),
+            successor: Some(next_hop.successor.into()),
+            finger: next_hop.finger.map(|finger| chord_proto::PrecedingFinger {
+                index: finger.index.into(),
+                node: Some(finger.node.into()),
+            }),
+        }
+    }
+}
+
 impl From<chord_rs_core::Node> for GetSuccessorResponse {
     fn from(node: chord_rs_core::Node) -> Self {
         GetSuccessorResponse {
@@ -145,6 +267,14 @@ impl From<chord_rs_core::Node> for GetSuccessorResponse {
     }
 }
 
+impl From<Vec<chord_rs_core::Node>> for GetSuccessorListResponse {
+    fn from(nodes: Vec<chord_rs_core::Node>) -> Self {
+        GetSuccessorListResponse {
+            nodes: nodes.into_iter().map(|node| node.into()).collect(),
+        }
+    }
+}
+
 impl From<Option<chord_rs_core::Node>> for GetPredecessorResponse {
     fn from(node: Option<chord_rs_core::Node>) -> Self {
         GetPredecessorResponse {
@@ -159,6 +289,7 @@ impl From<chord_rs_core::Node> for chord_proto::Node {
             id: node.id().into(),
             ip: Some(node.addr().ip().into()),
             port: node.addr().port() as i32,
+            host: node.host().unwrap_or_default().to_string(),
         }
     }
 }
@@ -0,0 +1,65 @@
+use std::path::Path;
+
+use chord_rs_core::client::{TlsConfig, TlsIdentity};
+use error_stack::{IntoReport, Report, Result, ResultExt};
+use thiserror::Error;
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to load TLS files")]
+    Load,
+    #[error("Invalid TLS configuration")]
+    InvalidConfig,
+}
+
+/// Create the TLS config of a client
+///
+/// # Arguments
+///
+/// * `config` - The TLS configuration, the identity is presented to servers requiring client certificates
+pub fn client_config(config: &TlsConfig) -> Result<ClientTlsConfig, TlsError> {
+    let mut tls = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(read(&config.ca_cert)?))
+        .domain_name(config.server_name.clone());
+
+    if let Some(identity) = &config.identity {
+        tls = tls.identity(identity_from(identity)?);
+    }
+
+    Ok(tls)
+}
+
+/// Create the TLS config of a server
+///
+/// # Arguments
+///
+/// * `config` - The TLS configuration, it has to contain the server identity
+pub fn server_config(config: &TlsConfig) -> Result<ServerTlsConfig, TlsError> {
+    let identity = config
+        .identity
+        .as_ref()
+        .ok_or_else(|| Report::new(TlsError::InvalidConfig))
+        .attach_printable("The server requires a certificate and a private key")?;
+
+    let mut tls = ServerTlsConfig::new().identity(identity_from(identity)?);
+    if config.client_auth {
+        tls = tls.client_ca_root(Certificate::from_pem(read(&config.ca_cert)?));
+    }
+
+    Ok(tls)
+}
+
+fn identity_from(identity: &TlsIdentity) -> Result<Identity, TlsError> {
+    Ok(Identity::from_pem(
+        read(&identity.cert)?,
+        read(&identity.key)?,
+    ))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(path)
+        .into_report()
+        .change_context(TlsError::Load)
+        .attach_printable_lazy(|| format!("Failed to read {:?}", path))
+}
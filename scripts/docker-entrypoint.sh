@@ -2,20 +2,16 @@
 
 set -e
 
-ARGS=()
-# If LEADER_HOST is not set, then we are the leader
-if [ -z "$LEADER_HOST" ]; then
-    echo "Starting leader"
-    # RING=""
-else
-    echo "Starting follower"
-    LEADER_IP=$(getent hosts $LEADER_HOST | awk '{ print $1 }')
-    ARGS+=("--ring" "$LEADER_IP:42000")
+# The nodes find each other with the multicast discovery,
+# SEEDS can point them to an existing ring instead
+ARGS=("--discovery")
+if [ -n "$SEEDS" ]; then
+    ARGS+=("--ring" "$SEEDS")
 fi
 
 MY_IP=$(hostname -i)
 
-ARGS+=("--listen" "$MY_IP:42000")
+ARGS+=("--listen" "0.0.0.0:42000" "--advertise" "$MY_IP:42000")
 
 echo "Starting with args: ${ARGS[@]}"
 
@@ -1,11 +1,13 @@
 #!/usr/bin/env bash
 
 # Usage:
-# ./run-nodes.sh -n [num_nodes] -p [start_port] -l [leader]
+# ./run-nodes.sh -n [num_nodes] -p [start_port] -l [seed]
 #
 # num_nodes: number of nodes to start (default: 3)
 # start_port: port to start on (default: 42000)
-# -l: leader host, if not set then the leader node will be started
+# -l: seed of an existing ring to join, if not set then the nodes form a new ring
+#
+# The nodes find each other with the multicast discovery, see `server --help`.
 
 set -e
 
@@ -22,7 +24,7 @@ while getopts "n:p:l:" opt; do
             START_PORT=$OPTARG
             ;;
         l)
-            LEADER=$OPTARG
+            SEED=$OPTARG
             ;;
         \?)
             echo "Invalid option: -$OPTARG" >&2
@@ -41,20 +43,12 @@ trap 'trap " " SIGTERM; kill 0; wait; cleanup' SIGINT SIGTERM
 echo "Starting $NUM_NODES nodes"
 echo "Start port: $START_PORT"
 
-if [ -z "$LEADER" ]; then
-    LEADER="$LISTEN_IP:$START_PORT"
-    ARGS+=("--listen" "$LEADER")
-    START_PORT=$((START_PORT + 1))
-    NUM_NODES=$((NUM_NODES - 1))
-
-    echo "Starting leader with args: ${ARGS[@]}"
-
-    nohup ./target/release/server ${ARGS[@]} &
-fi
-
 for i in $(seq 1 $NUM_NODES); do
-    ARGS=("--listen" "$LISTEN_IP:$START_PORT" "--ring" "$LEADER")
-    echo "Starting follower with args: ${ARGS[@]}"
+    ARGS=("--listen" "$LISTEN_IP:$START_PORT" "--discovery")
+    if [ -n "$SEED" ]; then
+        ARGS+=("--ring" "$SEED")
+    fi
+    echo "Starting node with args: ${ARGS[@]}"
 
     nohup ./target/release/server ${ARGS[@]} &
 
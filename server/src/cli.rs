@@ -1,7 +1,17 @@
-use std::net::SocketAddr;
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
-use chord_rs::Config;
-use clap::{arg, command, Parser, ValueEnum};
+use chord_rs::{
+    Address, CacheConfig, ClusterSecret, Config, DiscoveryConfig, NodeId, Seed, Timeouts,
+    TlsConfig, TlsIdentity,
+};
+use clap::{arg, command, Args, Parser, ValueEnum};
+
+use crate::identity;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -10,9 +20,43 @@ pub(crate) struct Cli {
     #[arg(short, long, value_name = "[ADDRESS[:PORT]]", default_value_t = SocketAddr::from(([127, 0, 0, 1], 42000)))]
     pub(crate) listen: SocketAddr,
 
-    /// Address of a node in the ring to join
-    #[arg(short, long, value_name = "[ADDRESS[:PORT]]")]
-    pub(crate) ring: Option<SocketAddr>,
+    /// Sets the socket address other nodes reach the node on, the node is identified by it
+    /// (default: the listen address)
+    #[arg(short, long, value_name = "ADDRESS:PORT")]
+    pub(crate) advertise: Option<SocketAddr>,
+
+    /// Sets the host name other nodes reach the node by, with the port of the advertised address.
+    /// It's resolved when the nodes connect, so the node stays reachable when its address changes
+    #[arg(long, value_name = "NAME")]
+    pub(crate) hostname: Option<String>,
+
+    /// Sets the id of the node, a decimal or a 0x prefixed hex number
+    /// (default: hash of the host name and port, or of the advertised address)
+    #[arg(long, value_name = "ID", value_parser = NodeId::from_str, conflicts_with = "node_name")]
+    pub(crate) node_id: Option<NodeId>,
+
+    /// Sets the id of the node to the hash of the name
+    #[arg(long, value_name = "NAME")]
+    pub(crate) node_name: Option<String>,
+
+    /// Directory the state of the node is kept in, the node keeps its id there
+    /// so that it stays the same when the node moves to another address
+    #[arg(long, value_name = "PATH")]
+    pub(crate) data_dir: Option<PathBuf>,
+
+    /// Addresses or host names of the nodes in the ring to join, tried in turn.
+    /// A host name is tried with every address it resolves to (default port: 42000)
+    #[arg(short, long, value_name = "HOST[:PORT]", value_delimiter = ',')]
+    pub(crate) ring: Vec<Seed>,
+
+    /// Transport used to communicate with other nodes
+    #[arg(short, long, value_enum, default_value_t = Transport::Capnp)]
+    pub(crate) transport: Transport,
+
+    /// Sets a socket address to serve the other transport on,
+    /// e.g. gRPC when the transport is capnp. Used to migrate a ring between transports
+    #[arg(long, value_name = "[ADDRESS[:PORT]]")]
+    pub(crate) secondary_listen: Option<SocketAddr>,
 
     /// Set the log level
     #[arg(short('L'), long, value_name = "LEVEL", value_enum, default_value_t = LogLevel::Info)]
@@ -22,6 +66,160 @@ pub(crate) struct Cli {
     /// (default: 1024)
     #[arg(long, value_name = "CONNECTIONS", default_value = "1024")]
     pub(crate) max_connections: usize,
+
+    /// Set the number of threads serving capnp connections
+    /// (default: number of CPUs)
+    #[arg(long, value_name = "THREADS", default_value_t = default_workers())]
+    pub(crate) workers: usize,
+
+    /// Set the number of connections waiting for a slot when the maximum is reached
+    /// (default: 128)
+    #[arg(long, value_name = "CONNECTIONS", default_value = "128")]
+    pub(crate) accept_queue: usize,
+
+    /// Set how long a queued connection waits for a slot before it's rejected
+    /// (default: 1000)
+    #[arg(long, value_name = "MILLISECONDS", default_value = "1000")]
+    pub(crate) accept_timeout: u64,
+
+    /// Set the timeout of the calls made to other nodes
+    /// (default: 2000)
+    #[arg(long, value_name = "MILLISECONDS", default_value = "2000")]
+    pub(crate) rpc_timeout: u64,
+
+    /// Set the timeout of a lookup, including the hops made by the other nodes
+    /// (default: 5000)
+    #[arg(long, value_name = "MILLISECONDS", default_value = "5000")]
+    pub(crate) lookup_timeout: u64,
+
+    /// Set how the finger table is kept up to date
+    #[arg(long, value_enum, default_value_t = FixFingers::SkipCovered)]
+    pub(crate) fix_fingers: FixFingers,
+
+    /// Set the maximum number of cached lookup results, 0 disables the cache
+    /// (default: 1024)
+    #[arg(long, value_name = "ENTRIES", default_value = "1024")]
+    pub(crate) lookup_cache_size: usize,
+
+    /// Set how long a cached lookup result is used
+    /// (default: 30000)
+    #[arg(long, value_name = "MILLISECONDS", default_value = "30000")]
+    pub(crate) lookup_cache_ttl: u64,
+
+    #[command(flatten)]
+    pub(crate) discovery: DiscoveryArgs,
+
+    #[command(flatten)]
+    pub(crate) tls: TlsArgs,
+
+    /// File containing the secret shared by the nodes of the ring.
+    /// Nodes reject notify calls from nodes which don't know it
+    #[arg(long, value_name = "PATH")]
+    pub(crate) cluster_secret_file: Option<PathBuf>,
+}
+
+fn default_workers() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Read the cluster secret, ignoring the trailing newline
+fn read_cluster_secret(path: &Path) -> std::io::Result<ClusterSecret> {
+    let secret = std::fs::read(path).map_err(|err| {
+        std::io::Error::new(
+            err.kind(),
+            format!("Failed to read the cluster secret {:?}: {}", path, err),
+        )
+    })?;
+    let len = secret
+        .iter()
+        .rposition(|byte| !byte.is_ascii_whitespace())
+        .map_or(0, |i| i + 1);
+    if len == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("The cluster secret {:?} is empty", path),
+        ));
+    }
+
+    Ok(ClusterSecret::new(&secret[..len]))
+}
+
+/// Multicast discovery is enabled with `--discovery`
+#[derive(Args)]
+pub(crate) struct DiscoveryArgs {
+    /// Announce the node over UDP multicast and join the ring of the announced nodes
+    /// when the node is alone
+    #[arg(long, default_value_t = false)]
+    pub(crate) discovery: bool,
+
+    /// Multicast group the nodes announce themselves to
+    #[arg(
+        long,
+        value_name = "ADDRESS:PORT",
+        default_value = "239.255.42.99:42999",
+        requires = "discovery"
+    )]
+    pub(crate) discovery_group: SocketAddrV4,
+
+    /// Address of the interface to announce on, chosen by the routing table by default
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        default_value = "0.0.0.0",
+        requires = "discovery"
+    )]
+    pub(crate) discovery_interface: Ipv4Addr,
+}
+
+impl From<DiscoveryArgs> for Option<DiscoveryConfig> {
+    fn from(args: DiscoveryArgs) -> Self {
+        args.discovery.then(|| DiscoveryConfig {
+            group: args.discovery_group,
+            interface: args.discovery_interface,
+            ..DiscoveryConfig::default()
+        })
+    }
+}
+
+/// TLS is enabled when the CA certificate is set
+#[derive(Args)]
+pub(crate) struct TlsArgs {
+    /// CA certificate (PEM) used to verify the certificates of other nodes
+    #[arg(long, value_name = "PATH", requires_all = ["tls_cert", "tls_key"])]
+    pub(crate) tls_ca: Option<PathBuf>,
+
+    /// Certificate (PEM) of the node, presented to clients and, with mutual TLS, to other nodes
+    #[arg(long, value_name = "PATH", requires = "tls_ca")]
+    pub(crate) tls_cert: Option<PathBuf>,
+
+    /// Private key (PEM) of the node certificate
+    #[arg(long, value_name = "PATH", requires = "tls_ca")]
+    pub(crate) tls_key: Option<PathBuf>,
+
+    /// Require clients to present a certificate signed by the CA (mutual TLS)
+    #[arg(long, default_value_t = false, requires = "tls_ca")]
+    pub(crate) tls_client_auth: bool,
+
+    /// DNS name the certificates of other nodes are issued for
+    #[arg(long, value_name = "NAME", default_value = "localhost")]
+    pub(crate) tls_server_name: String,
+}
+
+impl From<TlsArgs> for Option<TlsConfig> {
+    fn from(args: TlsArgs) -> Self {
+        let ca_cert = args.tls_ca?;
+        let identity = match (args.tls_cert, args.tls_key) {
+            (Some(cert), Some(key)) => Some(TlsIdentity { cert, key }),
+            _ => None,
+        };
+
+        Some(TlsConfig {
+            ca_cert,
+            identity,
+            client_auth: args.tls_client_auth,
+            server_name: args.tls_server_name,
+        })
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -33,12 +231,96 @@ pub(crate) enum LogLevel {
     Trace,
 }
 
-impl Into<Config> for Cli {
-    fn into(self) -> Config {
-        Config {
-            addr: self.listen,
-            ring: self.ring,
-            max_connections: self.max_connections,
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Transport {
+    Capnp,
+    Grpc,
+}
+
+/// Strategy used to fix the fingers, see [`chord_rs::FixFingers`]
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub(crate) enum FixFingers {
+    /// Look up every finger every second
+    All,
+    /// Look up every finger every second, unless the previous finger covers it
+    SkipCovered,
+    /// Look up one finger per second, in order
+    Incremental,
+    /// Look up one random finger per second
+    Random,
+}
+
+impl From<FixFingers> for chord_rs::FixFingers {
+    fn from(strategy: FixFingers) -> Self {
+        match strategy {
+            FixFingers::All => chord_rs::FixFingers::All,
+            FixFingers::SkipCovered => chord_rs::FixFingers::SkipCovered,
+            FixFingers::Incremental => chord_rs::FixFingers::Incremental,
+            FixFingers::Random => chord_rs::FixFingers::Random,
+        }
+    }
+}
+
+impl From<Transport> for chord_rs::Transport {
+    fn from(transport: Transport) -> Self {
+        match transport {
+            Transport::Capnp => chord_rs::Transport::Capnp,
+            Transport::Grpc => chord_rs::Transport::Grpc,
         }
     }
 }
+
+impl Cli {
+    /// The address other nodes reach the node on
+    fn address(&self) -> Address {
+        let advertise = self.advertise.unwrap_or(self.listen);
+        match &self.hostname {
+            Some(host) => Address::Host(host.clone(), advertise.port()),
+            None => advertise.into(),
+        }
+    }
+}
+
+impl TryFrom<Cli> for Config {
+    type Error = std::io::Error;
+
+    /// Fails when the node id can't be read from or kept in the data directory
+    fn try_from(cli: Cli) -> Result<Self, Self::Error> {
+        let node_id = identity::node_id(
+            cli.node_id
+                .or_else(|| cli.node_name.as_deref().map(NodeId::from_name)),
+            cli.data_dir.as_deref(),
+            cli.address(),
+        )?;
+
+        Ok(Config {
+            addr: cli.listen,
+            ring: cli.ring,
+            transport: cli.transport.into(),
+            secondary_addr: cli.secondary_listen,
+            tls: cli.tls.into(),
+            cluster_secret: cli
+                .cluster_secret_file
+                .as_deref()
+                .map(read_cluster_secret)
+                .transpose()?,
+            max_connections: cli.max_connections,
+            workers: cli.workers,
+            accept_queue: cli.accept_queue,
+            accept_timeout: Duration::from_millis(cli.accept_timeout),
+            timeouts: Timeouts {
+                find_successor: Duration::from_millis(cli.lookup_timeout),
+                ..Timeouts::all(Duration::from_millis(cli.rpc_timeout))
+            },
+            fix_fingers: cli.fix_fingers.into(),
+            discovery: cli.discovery.into(),
+            advertise: cli.advertise,
+            node_id,
+            host: cli.hostname,
+            cache: CacheConfig {
+                capacity: cli.lookup_cache_size,
+                ttl: Duration::from_millis(cli.lookup_cache_ttl),
+            },
+        })
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use chord_rs::{Address, NodeId};
+
+/// Name of the file in the data directory the node id is kept in
+const NODE_ID_FILE: &str = "node-id";
+
+/// Get the id of the node and keep it in the data directory
+///
+/// The id stays the same when the node restarts on another address. An explicit id replaces
+/// the kept one, otherwise the kept id is used, or the id is derived from the address on the
+/// first start. Without a data directory the id isn't kept, `None` lets the node derive it.
+///
+/// # Arguments
+///
+/// * `explicit` - The id set on the command line
+/// * `data_dir` - The directory the state of the node is kept in
+/// * `addr` - The address the node advertises, or its host name
+///
+/// # Errors
+///
+/// Fails when the kept id can't be read or parsed, or the id can't be kept
+pub(crate) fn node_id(
+    explicit: Option<NodeId>,
+    data_dir: Option<&Path>,
+    addr: Address,
+) -> io::Result<Option<NodeId>> {
+    let Some(data_dir) = data_dir else {
+        return Ok(explicit);
+    };
+
+    let path = data_dir.join(NODE_ID_FILE);
+    let error = |kind: ErrorKind, action: &str, err: &dyn std::fmt::Display| {
+        io::Error::new(
+            kind,
+            format!("Failed to {} the node id {:?}: {}", action, path, err),
+        )
+    };
+    let kept = match fs::read_to_string(&path) {
+        Ok(id) => Some(
+            id.trim()
+                .parse::<NodeId>()
+                .map_err(|err| error(ErrorKind::InvalidData, "read", &err))?,
+        ),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(error(err.kind(), "read", &err)),
+    };
+
+    let id = match (explicit, kept) {
+        (Some(explicit), Some(kept)) if explicit != kept => {
+            log::warn!(
+                "Replacing the node id {} kept in {:?} with {}",
+                kept,
+                path,
+                explicit
+            );
+            explicit
+        }
+        (Some(id), _) | (None, Some(id)) => id,
+        (None, None) => NodeId::from(&addr),
+    };
+
+    if kept != Some(id) {
+        fs::create_dir_all(data_dir)
+            .and_then(|_| fs::write(&path, format!("{}\n", id)))
+            .map_err(|err| error(err.kind(), "write", &err))?;
+    }
+
+    Ok(Some(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    fn addr(port: u16) -> Address {
+        SocketAddr::from(([127, 0, 0, 1], port)).into()
+    }
+
+    fn kept(data_dir: &Path) -> String {
+        fs::read_to_string(data_dir.join(NODE_ID_FILE)).unwrap()
+    }
+
+    #[test]
+    fn first_start_keeps_the_id_derived_from_the_address() {
+        let data_dir = tempfile::tempdir().unwrap();
+
+        let id = node_id(None, Some(data_dir.path()), addr(42001)).unwrap();
+
+        assert_eq!(id, Some(NodeId::from(&addr(42001))));
+        assert_eq!(kept(data_dir.path()), format!("{}\n", id.unwrap()));
+    }
+
+    #[test]
+    fn restart_on_another_address_reuses_the_kept_id() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let first = node_id(None, Some(data_dir.path()), addr(42001)).unwrap();
+
+        let id = node_id(None, Some(data_dir.path()), addr(42002)).unwrap();
+
+        assert_eq!(id, first);
+    }
+
+    #[test]
+    fn explicit_id_replaces_the_kept_one() {
+        let data_dir = tempfile::tempdir().unwrap();
+        node_id(None, Some(data_dir.path()), addr(42001)).unwrap();
+
+        let id = node_id(Some(NodeId::from(42)), Some(data_dir.path()), addr(42001)).unwrap();
+
+        assert_eq!(id, Some(NodeId::from(42)));
+        assert_eq!(kept(data_dir.path()), "42\n");
+        assert_eq!(
+            node_id(None, Some(data_dir.path()), addr(42001)).unwrap(),
+            Some(NodeId::from(42))
+        );
+    }
+
+    #[test]
+    fn unparsable_kept_id_fails() {
+        let data_dir = tempfile::tempdir().unwrap();
+        fs::write(data_dir.path().join(NODE_ID_FILE), "not an id").unwrap();
+
+        let err = node_id(None, Some(data_dir.path()), addr(42001)).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn without_data_dir_the_id_is_not_kept() {
+        assert_eq!(node_id(None, None, addr(42001)).unwrap(), None);
+        assert_eq!(
+            node_id(Some(NodeId::from(42)), None, addr(42001)).unwrap(),
+            Some(NodeId::from(42))
+        );
+    }
+}
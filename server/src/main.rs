@@ -4,6 +4,7 @@ use simplelog::{ColorChoice, CombinedLogger, Config, TermLogger, TerminalMode};
 use chord_rs::Server;
 
 mod cli;
+mod identity;
 use clap::Parser;
 use cli::Cli;
 
@@ -15,9 +16,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = cli.listen;
     println!("Listening on: {}", addr);
 
-    let server = Server::new(addr, cli).await;
+    let config = chord_rs::Config::try_from(cli).map_err(|err| err.to_string())?;
+    let server = Server::new(addr, config)
+        .await
+        .map_err(|report| format!("{report:#}"))?;
 
-    server.run().await;
+    server.run().await.map_err(|report| format!("{report:#}"))?;
     Ok(())
 }
 